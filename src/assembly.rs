@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::{Display as EnumDisplay, EnumIter, EnumString};
+
+use super::bom::{Bom, Item, ItemsTable, Layer, MountTechnology, SortBy};
+
+/// Process step of the assembly line a part goes through.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, EnumIter, EnumString, EnumDisplay,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AssemblyStep {
+    TopSmd,
+    BottomSmd,
+    Tht,
+    /// Parts without a mount technology, eg. screws or labels
+    Manual,
+}
+
+impl AssemblyStep {
+    /// Step of a part: THT, SMD on its layer (top if none) or manual.
+    pub fn of(item: &Item) -> AssemblyStep {
+        match (item.mount_technology(), item.layer()) {
+            (Some(MountTechnology::Tht), _) => AssemblyStep::Tht,
+            (Some(MountTechnology::Smd), Some(Layer::Bottom)) => AssemblyStep::BottomSmd,
+            (Some(MountTechnology::Smd), _) => AssemblyStep::TopSmd,
+            (None, _) => AssemblyStep::Manual,
+        }
+    }
+
+    /// Name for sheets and titles.
+    pub fn label(&self) -> &'static str {
+        match self {
+            AssemblyStep::TopSmd => "Top SMD",
+            AssemblyStep::BottomSmd => "Bottom SMD",
+            AssemblyStep::Tht => "THT",
+            AssemblyStep::Manual => "Manual",
+        }
+    }
+}
+
+/// The merged table of the parts of one step.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AssemblySplit {
+    pub step: AssemblyStep,
+    pub table: ItemsTable,
+}
+
+impl AssemblySplit {
+    /// Populated lines of the step.
+    pub fn lines(&self) -> usize {
+        self.table.rows.len()
+    }
+
+    /// Populated parts of the step, from the quantity column.
+    pub fn parts(&self) -> usize {
+        self.table.parts()
+    }
+}
+
+impl Bom {
+    /// Split the parts by assembly step, each step merged on its own so a
+    /// line has the quantity of that step only. Call it before merging: a
+    /// merged line keeps the layer of its first part. Steps without parts
+    /// are left out.
+    pub fn assembly_splits(&self, sort: SortBy) -> Vec<AssemblySplit> {
+        AssemblyStep::iter()
+            .filter_map(|step| {
+                let items = self
+                    .items()
+                    .filter(|i| AssemblyStep::of(i) == step)
+                    .cloned();
+                let mut bom = Bom::from_items(items, self.merge_keys()).merge();
+                (!bom.is_empty()).then(|| AssemblySplit {
+                    step,
+                    table: bom.odered_vector_table_by(sort),
+                })
+            })
+            .collect()
+    }
+}
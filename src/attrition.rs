@@ -0,0 +1,120 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::bom::{category_is, ItemView, ItemsTable};
+use super::project::load_config;
+
+pub const NET_QUANTITY: &str = "Net quantity";
+pub const GROSS_QUANTITY: &str = "Gross quantity";
+
+/// Spare parts to buy for the losses of the assembly, eg. 0402 parts get
+/// 10% more and at least 20. An empty `category` or `footprint` matches all.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AttritionRule {
+    /// Category name or designator letter: "Capacitors" or "C"
+    #[serde(default)]
+    pub category: String,
+    /// Part of the footprint, eg. "0402" matches "0402_[1005]"
+    #[serde(default)]
+    pub footprint: String,
+    #[serde(default)]
+    pub percent: f64,
+    #[serde(default)]
+    pub min_extra: usize,
+}
+
+impl AttritionRule {
+    fn matches(&self, category: &str, footprint: &str) -> bool {
+        let category_ok = self.category.is_empty() || category_is(category, &self.category);
+        let footprint_ok = self.footprint.is_empty()
+            || footprint
+                .to_lowercase()
+                .contains(&self.footprint.to_lowercase());
+        category_ok && footprint_ok
+    }
+
+    /// Spare parts for `net` parts, none when no part is needed.
+    pub fn extra(&self, net: usize) -> usize {
+        if net == 0 {
+            return 0;
+        }
+        // Keep 100 * 10% at 10 and not 11 after the float rounding
+        let percent = (net as f64 * self.percent / 100.0 - 1e-9).ceil().max(0.0) as usize;
+        percent.max(self.min_extra)
+    }
+}
+
+/// Rules tried in order, the first matching one applies.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AttritionRules {
+    #[serde(default)]
+    pub rules: Vec<AttritionRule>,
+}
+
+impl AttritionRules {
+    pub fn new(rules: Vec<AttritionRule>) -> AttritionRules {
+        AttritionRules { rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Load the rules from json or toml, by extension:
+    /// `[[rules]] footprint = "0402" percent = 10.0 min_extra = 20`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<AttritionRules> {
+        load_config(path, "attrition rules")
+    }
+
+    /// Quantity to buy for `net` parts of a line.
+    pub fn gross(&self, net: usize, category: &str, footprint: &str) -> usize {
+        let extra = self
+            .rules
+            .iter()
+            .find(|r| r.matches(category, footprint))
+            .map_or(0, |r| r.extra(net));
+        net + extra
+    }
+}
+
+impl ItemsTable {
+    /// Append the net quantity for `build_quantity` boards and the gross one,
+    /// with the spare parts of `rules`. Prices and stock checks use the gross
+    /// quantity when the table has it.
+    pub fn add_attrition(&mut self, rules: &AttritionRules, build_quantity: usize) {
+        let footprint = self.headers.iter().position(|h| h == "Footprint");
+        for row in self.rows.iter_mut() {
+            let net = per_board(row) * build_quantity;
+            let fp = footprint
+                .and_then(|i| row.fields.get(i))
+                .map_or("", |s| s.as_str());
+            let gross = rules.gross(net, &row.category, fp);
+            row.fields.push(net.to_string());
+            row.fields.push(gross.to_string());
+        }
+        for row in self.dnp.iter_mut() {
+            row.fields.push(String::new());
+            row.fields.push(String::new());
+        }
+        self.headers.push(NET_QUANTITY.to_string());
+        self.headers.push(GROSS_QUANTITY.to_string());
+    }
+
+    /// Parts to buy for each row: the gross quantity if any, or the first
+    /// column for `build_quantity` boards.
+    pub(crate) fn purchase_quantities(&self, build_quantity: usize) -> Vec<usize> {
+        let gross = self.headers.iter().position(|h| h == GROSS_QUANTITY);
+        self.rows
+            .iter()
+            .map(|row| match gross.and_then(|i| row.fields.get(i)) {
+                Some(q) => q.parse().unwrap_or(0),
+                None => per_board(row) * build_quantity,
+            })
+            .collect()
+    }
+}
+
+fn per_board(row: &ItemView) -> usize {
+    row.fields.first().and_then(|q| q.parse().ok()).unwrap_or(0)
+}
@@ -0,0 +1,460 @@
+use clap::{Args, Parser, Subcommand};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+
+use mergebom_web::{
+    attrition::AttritionRules,
+    bom::{Bom, ItemsTable, Layer, SortBy},
+    columns::ColumnSpec,
+    distributor::Distributor,
+    inventory::Inventory,
+    library::PartsLibrary,
+    lookup::{DiskCache, HttpBackend, PartLookup},
+    outjob::{OutFormat, OutJobXlsx, XlsxOptions, XlsxSheets},
+    placement::Placements,
+    project::MergeProject,
+    style::XlsxStyle,
+    ASCII_LOGO,
+};
+
+#[derive(Parser)]
+#[command(author, version, about = "Pretty merger and formatter Bill Of Materials.", before_help = ASCII_LOGO)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Args)]
+struct XlsxArgs {
+    /// Add cover, diagnostics and per source sheets to the xlsx
+    #[arg(long)]
+    assembly: bool,
+    /// Style of the xlsx tables (json or toml)
+    #[arg(long)]
+    style: Option<PathBuf>,
+}
+
+impl XlsxArgs {
+    fn options(&self) -> anyhow::Result<XlsxOptions> {
+        let mut options = XlsxOptions::default();
+        if self.assembly {
+            options.sheets = XlsxSheets::all();
+        }
+        if let Some(style) = &self.style {
+            options.style = XlsxStyle::load(style)?;
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Merge BOM files (csv, xls, xlsx) in one xlsx
+    Merge {
+        /// Fields used to merge rows
+        #[arg(short, long, default_values_t = ["comment".to_string(), "footprint".to_string()])]
+        keys: Vec<String>,
+        /// Output file, the extension of the format is added if it has none
+        #[arg(short, long, default_value = "merged_bom")]
+        output: PathBuf,
+        /// xlsx, csv, json, markdown, html or report (html to print)
+        #[arg(short, long, default_value_t = OutFormat::Xlsx)]
+        format: OutFormat,
+        #[command(flatten)]
+        xlsx: XlsxArgs,
+        /// Board revision, shown in the outputs
+        #[arg(short, long, default_value = "")]
+        revision: String,
+        /// Add one quantity column per source file
+        #[arg(long)]
+        sources: bool,
+        /// Order of the lines inside a category: designator, value or quantity
+        #[arg(short, long, default_value_t = SortBy::Designator)]
+        sort: SortBy,
+        /// Also save the merge as a project file (.json or .toml)
+        #[arg(short, long)]
+        project: Option<PathBuf>,
+        /// Price list (csv or xlsx) to add unit and extended prices
+        #[arg(long)]
+        prices: Option<PathBuf>,
+        /// Boards to build, for the extended prices
+        #[arg(short, long, default_value_t = 1)]
+        build_qty: usize,
+        /// Parts library (json) to fill the blank fields
+        #[arg(long)]
+        library: Option<PathBuf>,
+        /// Attrition rules (json or toml) adding spare parts to the quantities
+        #[arg(long)]
+        attrition: Option<PathBuf>,
+        /// Columns of the output (json or toml): which, in which order, how named
+        #[arg(long)]
+        columns: Option<PathBuf>,
+        /// Distributor API to look up stock, lifecycle and price by MPN
+        #[arg(long)]
+        lookup_url: Option<String>,
+        /// Directory caching the lookup answers for a day
+        #[arg(long, requires = "lookup_url")]
+        lookup_cache: Option<PathBuf>,
+        files: Vec<PathBuf>,
+    },
+    /// Write the merged BOM of a project file (.json or .toml)
+    Project {
+        /// Merge the inputs again and update the project file
+        #[arg(short, long)]
+        run: bool,
+        /// Output file, the extension of the format is added if it has none
+        #[arg(short, long, default_value = "merged_bom")]
+        output: PathBuf,
+        /// xlsx, csv, json, markdown, html or report (html to print)
+        #[arg(short, long, default_value_t = OutFormat::Xlsx)]
+        format: OutFormat,
+        #[command(flatten)]
+        xlsx: XlsxArgs,
+        file: PathBuf,
+    },
+    /// Write the cart upload csv of a distributor from the merged BOM
+    Cart {
+        /// farnell, mouser or digikey
+        #[arg(short, long)]
+        distributor: Distributor,
+        /// Fields used to merge rows
+        #[arg(short, long, default_values_t = ["comment".to_string(), "footprint".to_string()])]
+        keys: Vec<String>,
+        /// Output csv, "<distributor>_cart.csv" by default
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        files: Vec<PathBuf>,
+    },
+    /// Check the merged BOM against the warehouse stock
+    Stock {
+        /// Inventory file (csv or xlsx) with code, location and quantity
+        #[arg(short, long)]
+        inventory: PathBuf,
+        /// Boards to build
+        #[arg(short, long, default_value_t = 1)]
+        build_qty: usize,
+        /// Fields used to merge rows
+        #[arg(short, long, default_values_t = ["comment".to_string(), "footprint".to_string()])]
+        keys: Vec<String>,
+        /// Parts library (json) to find the internal codes
+        #[arg(long)]
+        library: Option<PathBuf>,
+        /// Attrition rules (json or toml) adding spare parts to the quantities
+        #[arg(long)]
+        attrition: Option<PathBuf>,
+        /// Also write the report as xlsx
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Also write the report as json
+        #[arg(long)]
+        json: Option<PathBuf>,
+        files: Vec<PathBuf>,
+    },
+    /// Check pick-and-place files against the merged BOM and write a
+    /// centroid csv per side with the BOM values
+    Place {
+        /// Pick-and-place files: Altium csv or txt, KiCad pos or csv
+        #[arg(long, num_args = 1.., required = true)]
+        pnp: Vec<PathBuf>,
+        /// Fields used to merge rows
+        #[arg(short, long, default_values_t = ["comment".to_string(), "footprint".to_string()])]
+        keys: Vec<String>,
+        /// Prefix of the outputs, "<prefix>_top.csv" and "<prefix>_bottom.csv"
+        #[arg(short, long, default_value = "centroid")]
+        output: String,
+        files: Vec<PathBuf>,
+    },
+    /// Split the BOM by assembly step: top SMD, bottom SMD, THT and manual
+    Split {
+        /// Fields used to merge rows
+        #[arg(short, long, default_values_t = ["comment".to_string(), "footprint".to_string()])]
+        keys: Vec<String>,
+        /// Output file, "<stem>_<step>.<extension>" of it with --files
+        #[arg(short, long, default_value = "assembly")]
+        output: PathBuf,
+        /// xlsx, csv, json, markdown, html or report (html to print)
+        #[arg(short, long, default_value_t = OutFormat::Xlsx)]
+        format: OutFormat,
+        #[command(flatten)]
+        xlsx: XlsxArgs,
+        /// One file per step, the only choice for the formats but xlsx
+        #[arg(long)]
+        files: bool,
+        /// Order of the lines inside a category: designator, value or quantity
+        #[arg(short, long, default_value_t = SortBy::Designator)]
+        sort: SortBy,
+        inputs: Vec<PathBuf>,
+    },
+    /// Show what changed between two revisions of a BOM
+    Diff {
+        /// Files of the old revision
+        #[arg(long, num_args = 1.., required = true)]
+        old: Vec<PathBuf>,
+        /// Files of the new revision
+        #[arg(long, num_args = 1.., required = true)]
+        new: Vec<PathBuf>,
+        /// Fields used to merge rows, the same for both revisions
+        #[arg(short, long, default_values_t = ["comment".to_string(), "footprint".to_string()])]
+        keys: Vec<String>,
+        /// Also write the diff as xlsx
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+fn main() {
+    env_logger::init();
+    let cli = Cli::parse();
+
+    match cli.command {
+        Commands::Merge {
+            keys,
+            output,
+            format,
+            xlsx,
+            revision,
+            sources,
+            sort,
+            project,
+            prices,
+            build_qty,
+            library,
+            attrition,
+            columns,
+            lookup_url,
+            lookup_cache,
+            files,
+        } => {
+            let mut prj = MergeProject::new(files.as_slice(), &keys);
+            prj.options.sort_by = sort;
+            prj.options.source_columns = sources;
+            prj.options.revision = revision;
+            prj.options.price_list = prices;
+            prj.options.build_quantity = build_qty;
+            prj.options.library = library;
+            if let Some(attrition) = attrition {
+                prj.options.attrition =
+                    AttritionRules::load(attrition).unwrap_or_else(|e| exit_with(e));
+            }
+            if let Some(columns) = columns {
+                prj.options.columns = ColumnSpec::load(columns).unwrap_or_else(|e| exit_with(e));
+            }
+            let mut data = prj.run().unwrap_or_else(|e| exit_with(e));
+            for d in data.diagnostics.iter() {
+                eprintln!("{}", d);
+            }
+            if let Some(url) = lookup_url {
+                let backend = HttpBackend::new(&url).unwrap_or_else(|e| exit_with(e));
+                let mut lookup = PartLookup::new(backend);
+                if let Some(dir) = lookup_cache {
+                    lookup = lookup.with_cache(DiskCache::new(dir, Duration::from_secs(24 * 3600)));
+                }
+                let known = data.diagnostics.len();
+                lookup.annotate(&mut data, build_qty);
+                for d in data.diagnostics.iter().skip(known) {
+                    eprintln!("{}", d);
+                }
+            }
+            if let Some(project) = project {
+                prj.save(project).unwrap_or_else(|e| exit_with(e));
+            }
+            write_out_job(&prj, data, format, &xlsx, &output);
+        }
+        Commands::Project {
+            run,
+            output,
+            format,
+            xlsx,
+            file,
+        } => {
+            let mut prj = MergeProject::load(&file).unwrap_or_else(|e| exit_with(e));
+            let data = if run {
+                let data = prj.run().unwrap_or_else(|e| exit_with(e));
+                prj.save(&file).unwrap_or_else(|e| exit_with(e));
+                data
+            } else {
+                prj.table().unwrap_or_else(|e| exit_with(e))
+            };
+            write_out_job(&prj, data, format, &xlsx, &output);
+        }
+        Commands::Cart {
+            distributor,
+            keys,
+            output,
+            files,
+        } => {
+            let bom = Bom::loader(files.as_slice(), &keys).merge();
+            for issue in bom.order_code_issues() {
+                eprintln!("{}", issue);
+            }
+            let output =
+                output.unwrap_or_else(|| PathBuf::from(format!("{}_cart.csv", distributor)));
+            let file = File::create(&output).unwrap_or_else(|e| exit_with(e.into()));
+            let lines = bom
+                .write_cart(distributor, file)
+                .unwrap_or_else(|e| exit_with(e));
+            println!("{}: {} lines", output.display(), lines);
+        }
+        Commands::Stock {
+            inventory,
+            build_qty,
+            keys,
+            library,
+            attrition,
+            output,
+            json,
+            files,
+        } => {
+            let inventory = Inventory::load(inventory).unwrap_or_else(|e| exit_with(e));
+            let mut bom = Bom::loader(files.as_slice(), &keys);
+            if let Some(library) = library {
+                let library = PartsLibrary::load(library).unwrap_or_else(|e| exit_with(e));
+                bom.enrich(&library);
+            }
+            let rules = match attrition {
+                Some(path) => AttritionRules::load(path).unwrap_or_else(|e| exit_with(e)),
+                None => AttritionRules::default(),
+            };
+            let report = bom.merge().shortage_report(&inventory, build_qty, &rules);
+            for l in report.shortages() {
+                println!(
+                    "{} [{}]: {} required, {} available, {} missing",
+                    l.unique_id,
+                    l.designators.join(", "),
+                    l.required,
+                    l.available,
+                    l.missing
+                );
+            }
+            if let Some(json) = json {
+                let data =
+                    serde_json::to_string_pretty(&report).unwrap_or_else(|e| exit_with(e.into()));
+                std::fs::write(json, data).unwrap_or_else(|e| exit_with(e.into()));
+            }
+            if let Some(output) = output {
+                OutJobXlsx::new(output)
+                    .and_then(|mut job| job.write_shortage(&report))
+                    .unwrap_or_else(|e| exit_with(e));
+            }
+        }
+        Commands::Place {
+            pnp,
+            keys,
+            output,
+            files,
+        } => {
+            let mut placements = Placements::new();
+            for path in pnp.iter() {
+                let loaded = Placements::load(path).unwrap_or_else(|e| exit_with(e));
+                for p in loaded.placements() {
+                    if !placements.add(p.clone()) {
+                        eprintln!("{}: {} placed twice", path.display(), p.designator);
+                    }
+                }
+            }
+            let bom = Bom::loader(files.as_slice(), &keys).merge();
+            for d in bom.check_placements(&placements) {
+                eprintln!("{}", d);
+            }
+            let centroid = bom.centroid(&placements);
+            for side in [Layer::Top, Layer::Bottom] {
+                let path = format!("{}_{}.csv", output, side.to_string().to_lowercase());
+                let file = File::create(&path).unwrap_or_else(|e| exit_with(e.into()));
+                let parts = centroid
+                    .write_csv(side, file)
+                    .unwrap_or_else(|e| exit_with(e));
+                println!("{}: {} parts", path, parts);
+            }
+        }
+        Commands::Split {
+            keys,
+            output,
+            format,
+            xlsx,
+            files,
+            sort,
+            inputs,
+        } => {
+            let prj = MergeProject::new(inputs.as_slice(), &keys);
+            let splits = Bom::loader(inputs.as_slice(), &keys).assembly_splits(sort);
+            for split in splits.iter() {
+                println!(
+                    "{}: {} lines, {} parts",
+                    split.step.label(),
+                    split.lines(),
+                    split.parts()
+                );
+            }
+            if format == OutFormat::Xlsx && !files {
+                let output = match output.extension() {
+                    Some(_) => output,
+                    None => output.with_extension(format.extension()),
+                };
+                let title = output
+                    .file_stem()
+                    .map_or(String::new(), |s| s.to_string_lossy().to_string());
+                xlsx.options()
+                    .and_then(|o| Ok(OutJobXlsx::new(&output)?.with_options(&o)))
+                    .and_then(|mut job| job.write_splits(&splits, &prj.out_job_meta(&title)))
+                    .unwrap_or_else(|e| exit_with(e));
+            } else {
+                // bom.xlsx gives bom_top_smd.xlsx, bom_tht.xlsx, ...
+                let stem = output
+                    .file_stem()
+                    .map_or(String::new(), |s| s.to_string_lossy().to_string());
+                for split in splits {
+                    let name = output
+                        .with_file_name(format!("{}_{}", stem, split.step))
+                        .with_extension(format.extension());
+                    write_out_job(&prj, split.table, format, &xlsx, &name);
+                }
+            }
+        }
+        Commands::Diff {
+            old,
+            new,
+            keys,
+            output,
+        } => {
+            let old = Bom::loader(old.as_slice(), &keys);
+            let new = Bom::loader(new.as_slice(), &keys);
+            let diff = old.diff(&new);
+            print!("{}", diff);
+            if let Some(output) = output {
+                OutJobXlsx::new(output)
+                    .and_then(|mut job| job.write_diff(&diff))
+                    .unwrap_or_else(|e| exit_with(e));
+            }
+        }
+    }
+}
+
+fn write_out_job(
+    prj: &MergeProject,
+    mut data: ItemsTable,
+    format: OutFormat,
+    xlsx: &XlsxArgs,
+    output: &Path,
+) {
+    let title = output
+        .file_stem()
+        .map_or(String::new(), |s| s.to_string_lossy().to_string());
+    let output = match output.extension() {
+        Some(_) => output.to_path_buf(),
+        None => output.with_extension(format.extension()),
+    };
+    data.apply_columns(&prj.options.columns);
+    xlsx.options()
+        .and_then(|o| format.out_job(&output, &o))
+        .and_then(|mut job| job.write(&data, &prj.out_job_meta(&title)))
+        .unwrap_or_else(|e| exit_with(e));
+}
+
+fn exit_with(e: anyhow::Error) -> ! {
+    eprintln!("Error: {:#}", e);
+    process::exit(1);
+}
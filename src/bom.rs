@@ -1,13 +1,28 @@
 use anyhow::{bail, Result};
-use calamine::{open_workbook_auto, DataType, Reader};
+use calamine::{open_workbook_auto, DataType, Reader, Xls, Xlsx};
+use lazy_static::lazy_static;
 use log::{debug, info, warn};
 use rand::{Rng, SeedableRng};
 use rand_pcg::Pcg32;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
-use std::{cmp::Ordering, collections::HashMap, ffi::OsStr, fmt, path::Path, vec};
-use strum_macros::EnumIter;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    ffi::OsStr,
+    fmt,
+    io::{Read, Seek},
+    path::Path,
+    str::FromStr,
+    vec,
+};
+use strum::IntoEnumIterator;
+use strum_macros::{Display as EnumDisplay, EnumIter, EnumString};
+
+use crate::cost::CostSummary;
+use crate::distributor::{order_codes, Distributor};
+use crate::utils::{comment_to_number, is_dnp_marker, natural_cmp, reference_column};
 
 fn uppercase_first_letter(s: &str) -> String {
     let mut c = s.chars();
@@ -17,9 +32,38 @@ fn uppercase_first_letter(s: &str) -> String {
     }
 }
 
+/// Header key of a manufacturer or MPN column, "manufacturer" and "mpn" for
+/// the main part, "manufacturer 2", "mpn 2" and so on for the alternates.
+fn part_header(item: &str) -> Option<String> {
+    lazy_static! {
+        static ref RE_PART: Regex = Regex::new(
+            r"^(?:(alt(?:ernate)?)\s+)?(manufacturer|mfr|mpn|manufacturer part number|mfr part number|manufacturer pn|mfr pn)(?:\s*(\d+))?$"
+        )
+        .unwrap();
+    }
+    let cc = RE_PART.captures(item.trim())?;
+    let name = match &cc[2] {
+        "manufacturer" | "mfr" => "manufacturer",
+        _ => "mpn",
+    };
+    let mut n: usize = cc.get(3).map_or(1, |m| m.as_str().parse().unwrap_or(1));
+    // "Alt MPN" is the first alternate, so the second part of the line
+    if cc.get(1).is_some() {
+        n += 1;
+    }
+    Some(match n {
+        0 | 1 => name.to_string(),
+        n => format!("{} {}", name, n),
+    })
+}
+
 fn is_header_key(item: &str) -> Result<String> {
     let re_note = Regex::new(r"(note|code)\s(.*)").unwrap();
 
+    if let Some(part) = part_header(&item.to_lowercase()) {
+        return Ok(uppercase_first_letter(&part));
+    }
+
     match item.to_lowercase().as_str() {
         "designator" | "comment" | "footprint" | "description" | "layer" => {
             //println!("Standard: {}", item);
@@ -29,6 +73,8 @@ fn is_header_key(item: &str) -> Result<String> {
             //println!("Standard: {}", item);
             Ok("MountTechnology".to_string())
         }
+        "dnp" | "dnf" | "np" | "do not populate" => Ok("Dnp".to_string()),
+        "fitted" | "populated" | "populate" | "variant" => Ok("Fitted".to_string()),
         _ => {
             let res: String;
             match re_note.captures(item.to_lowercase().as_ref()) {
@@ -45,66 +91,158 @@ fn is_header_key(item: &str) -> Result<String> {
     }
 }
 
-fn xlsx_loader<P: AsRef<Path>>(path: P) -> (Vec<Vec<String>>, HeaderMap) {
+fn xlsx_loader<P: AsRef<Path>>(path: P) -> (String, Vec<Row>, HeaderMap) {
     let mut workbook = match open_workbook_auto(path) {
         Ok(wb) => wb,
         Err(e) => panic!("{} Error while parsing file", e),
     };
-    let sheet_name = match workbook.sheet_names().first() {
+    match workbook_rows(&mut workbook) {
+        Ok(r) => r,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// Name defined by `OutJobXlsx` in its workbooks, pointing at the lines.
+pub const MERGED_NAME: &str = "MergedBOM";
+
+/// Name defined by `OutJobXlsx` for the header of the quantity column, so a
+/// relabelled quantity is found again.
+pub const MERGED_QUANTITY_NAME: &str = "MergedBOMQuantity";
+
+/// Sheet of the lines in the xlsx of `OutJobXlsx`, after its cover if any.
+pub const MERGED_SHEET: &str = "BOM";
+
+/// Whether a row of our own xlsx is a line, and not a category title, a
+/// subtotal ("4", "2 lines"), a blank row or a row of the cost summary.
+/// The extra rows are told by their text, as `OutJobXlsx` writes them, since
+/// a column spec can put them under any header.
+fn is_merged_line(row: &[String], headers: &HeaderMap) -> bool {
+    let is_category = |s: &str| Category::iter().any(|c| c.to_string() == s);
+    let is_subtotal = |s: &str| {
+        s.strip_suffix(" lines")
+            .or_else(|| s.strip_suffix(" line"))
+            .and_then(|n| n.parse::<usize>().ok())
+            .is_some()
+    };
+    let is_cost_label = |s: &str| {
+        is_category(s) || s == "Build quantity" || s == "Total" || s.starts_with("Total ")
+    };
+    let cells: Vec<&str> = row
+        .iter()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect();
+    let extra = match cells.as_slice() {
+        [title] => is_category(title) || title.ends_with(" without price"),
+        [label, value] => is_cost_label(label) && value.parse::<f64>().is_ok(),
+        _ => false,
+    };
+    // A line has more than a quantity
+    let mut fields = row
+        .iter()
+        .enumerate()
+        .filter(|(i, s)| !s.trim().is_empty() && headers.contains_key(i));
+    !extra
+        && !cells.iter().any(|s| is_subtotal(s))
+        && fields.any(|(i, _)| headers[&i] != "Quantity")
+}
+
+/// Rows of the first sheet of an open workbook, with the sheet name. A
+/// merged xlsx of ours, known by its `MERGED_NAME`, is read from its "BOM"
+/// sheet: the quantity column is kept and only the lines are rows, so it
+/// can be merged again. Its DNP sheet is not read.
+fn workbook_rows<W: Reader>(workbook: &mut W) -> Result<(String, Vec<Row>, HeaderMap)> {
+    let names = workbook.sheet_names().to_vec();
+    let defined = workbook.defined_names().to_vec();
+    let merged =
+        defined.iter().any(|(n, _)| n == MERGED_NAME) && names.iter().any(|n| n == MERGED_SHEET);
+    let quantity = defined
+        .iter()
+        .find(|(n, _)| n == MERGED_QUANTITY_NAME)
+        .and_then(|(_, r)| reference_column(r))
+        .filter(|_| merged);
+    let sheet_name = match names.first() {
+        _ if merged => MERGED_SHEET.to_string(),
         Some(name) => name.to_string(),
-        None => panic!("No sheet found in file"),
+        None => bail!("No sheet found in file"),
     };
 
     let mut headers: HeaderMap = HashMap::new();
-    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut rows: Vec<Row> = Vec::new();
     match workbook.worksheet_range(sheet_name.as_str()) {
         Some(Ok(range)) => {
             let (rw, cl) = range.get_size();
+            let first_row = range.start().map_or(0, |s| s.0 as usize);
+            let first_column = range.start().map_or(0, |s| s.1 as usize);
             for row in 0..rw {
+                // Once the header row is found, values like "DNP" or "Fitted"
+                // are data and must not be taken as header keys.
+                let header_found = !headers.is_empty();
                 let mut element = Vec::new();
                 for column in 0..cl {
                     let s = match range.get((row, column)) {
                         Some(DataType::String(s)) => s.to_string(),
                         Some(DataType::Int(s)) => s.to_string(),
                         Some(DataType::Float(s)) => s.to_string(),
+                        Some(DataType::Empty) | None if merged => String::new(),
                         _ => "-".to_string(),
                     };
-                    if let Ok(m) = is_header_key(&s) {
-                        headers.insert(column, m);
-                    } else {
-                        element.push(s);
+                    let key = match quantity {
+                        Some(q) if q == first_column + column => Ok("Quantity".to_string()),
+                        _ => is_header_key(&s),
+                    };
+                    match key {
+                        Ok(m) if !header_found => {
+                            headers.insert(column, m);
+                        }
+                        _ => element.push(s),
                     }
                 }
+                if merged && !(header_found && is_merged_line(&element, &headers)) {
+                    continue;
+                }
                 if !element.is_empty() {
-                    rows.push(element.clone());
+                    rows.push((first_row + row + 1, element.clone()));
                 }
             }
         }
-        _ => panic!("Male.."),
+        _ => bail!("Unable to read sheet {}", sheet_name),
     }
-    (rows, headers)
+    Ok((sheet_name, rows, headers))
 }
 
-fn csv_loader<P: AsRef<Path>>(path: P) -> (Vec<Vec<String>>, HeaderMap) {
-    let mut rd = match csv::ReaderBuilder::new().has_headers(false).from_path(path) {
+fn csv_loader<P: AsRef<Path>>(path: P) -> (Vec<Row>, HeaderMap) {
+    let rd = match csv::ReaderBuilder::new().has_headers(false).from_path(path) {
         Ok(r) => r,
         Err(e) => panic!("No file found {}", e),
     };
+    csv_rows(rd)
+}
 
-    let mut rows: Vec<Vec<String>> = Vec::new();
+fn csv_rows<R: Read>(mut rd: csv::Reader<R>) -> (Vec<Row>, HeaderMap) {
+    let mut rows: Vec<Row> = Vec::new();
     let mut headers: HeaderMap = HashMap::new();
 
-    for line in rd.records().flatten() {
+    for (n, line) in rd.records().enumerate() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                warn!("Skip line {}: {}", n + 1, e);
+                continue;
+            }
+        };
+        let header_found = !headers.is_empty();
         let mut element = Vec::new();
         for (i, s) in line.iter().enumerate() {
-            if let Ok(m) = is_header_key(s) {
-                headers.insert(i, m);
-            } else {
-                element.push(s.to_string());
+            match is_header_key(s) {
+                Ok(m) if !header_found => {
+                    headers.insert(i, m);
+                }
+                _ => element.push(s.to_string()),
             }
         }
         if !element.is_empty() {
-            rows.push(element.clone());
+            rows.push((n + 1, element.clone()));
         }
     }
     (rows, headers)
@@ -117,6 +255,18 @@ pub struct ItemView {
     pub is_np: bool,
     pub category: String,
     pub fields: Vec<String>,
+    pub sources: Vec<Source>,
+}
+
+/// Where a row comes from: file, sheet (empty for csv), row number in the
+/// file (1 based) and what the row had before merging.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Source {
+    pub file: String,
+    pub sheet: String,
+    pub row: usize,
+    pub designators: Vec<String>,
+    pub quantity: usize,
 }
 
 impl Display for ItemView {
@@ -130,15 +280,142 @@ impl Display for ItemView {
     }
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct ItemsTable {
     pub headers: Vec<String>,
     pub rows: Vec<ItemView>,
+    /// Not populated parts, kept out of `rows` so they are never purchased.
+    pub dnp: Vec<ItemView>,
+    /// Totals of `add_costs`, None for a table without prices.
+    #[serde(default)]
+    pub cost: Option<CostSummary>,
+    /// Problems found on the lines, eg. by a part lookup.
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+    /// Column of the quantity per board, the first one until `apply_columns`
+    /// moves it or drops it.
+    #[serde(default)]
+    pub quantity: Option<usize>,
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, EnumDisplay,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A problem of a table line, `unique_id` is the line.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Diagnostic {
+    pub unique_id: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.unique_id, self.message)
+    }
+}
+
+impl ItemView {
+    /// Quantity in `column` of `ItemsTable::quantity_column`, 0 without one.
+    pub fn quantity_in(&self, column: Option<usize>) -> usize {
+        column
+            .and_then(|i| self.fields.get(i))
+            .and_then(|q| q.parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+impl ItemsTable {
+    /// Column of the quantity per board, whatever its label.
+    pub fn quantity_column(&self) -> Option<usize> {
+        self.quantity
+    }
+
+    /// Parts of the populated rows, from the quantity column.
+    pub fn parts(&self) -> usize {
+        let column = self.quantity_column();
+        self.rows.iter().map(|r| r.quantity_in(column)).sum()
+    }
+
+    /// Append one quantity column per source file, so a line merged from
+    /// several boards shows how many parts each of them needs.
+    pub fn add_source_columns(&mut self) {
+        let mut files: Vec<String> = Vec::new();
+        for r in self.rows.iter().chain(self.dnp.iter()) {
+            for s in r.sources.iter() {
+                if !files.contains(&s.file) {
+                    files.push(s.file.clone());
+                }
+            }
+        }
+
+        for r in self.rows.iter_mut().chain(self.dnp.iter_mut()) {
+            for f in files.iter() {
+                let qty: usize = r
+                    .sources
+                    .iter()
+                    .filter(|s| s.file == *f)
+                    .map(|s| s.quantity)
+                    .sum();
+                r.fields.push(format!("{}", qty));
+            }
+        }
+        self.headers.extend(files);
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Bom {
     items: Vec<Item>,
+    #[serde(default)]
+    merge_keys: Vec<String>,
+    #[serde(default)]
+    variants: Vec<Variant>,
+}
+
+type Overrides = Vec<(String, String)>;
+
+/// Assembly variant: a populated configuration of the same source BOM.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Variant {
+    pub name: String,
+    /// Designators populated in this variant even if the source marks them DNP.
+    #[serde(default)]
+    pub fitted: Vec<String>,
+    /// Designators not populated in this variant.
+    #[serde(default)]
+    pub unfitted: Vec<String>,
+    /// Designator -> (field -> value) replacing the source fields, eg. another comment.
+    #[serde(default)]
+    pub substitutions: HashMap<String, HashMap<String, String>>,
+}
+
+impl Variant {
+    /// Fields to override for `designator` in this variant, sorted by name.
+    fn overrides(&self, designator: &str) -> Overrides {
+        let mut ov: Overrides = Vec::new();
+        if self.unfitted.iter().any(|d| d == designator) {
+            ov.push(("dnp".to_string(), "DNP".to_string()));
+        } else if self.fitted.iter().any(|d| d == designator) {
+            ov.push(("dnp".to_string(), "no".to_string()));
+            ov.push(("fitted".to_string(), "Fitted".to_string()));
+        }
+        if let Some(fields) = self.substitutions.get(designator) {
+            for (k, v) in fields.iter() {
+                ov.push((k.to_lowercase(), v.clone()));
+            }
+        }
+        ov.sort();
+        ov
+    }
 }
 
 const STD_HEADERS: [&str; 7] = [
@@ -151,9 +428,22 @@ const STD_HEADERS: [&str; 7] = [
     "mounttechnology",
 ];
 
+/// Part number columns, emitted after the standard ones when a BOM has them.
+const PART_HEADERS: [&str; 2] = ["manufacturer", "mpn"];
+
+/// Columns that only mark a part as not populated, they are not emitted.
+const DNP_HEADERS: [&str; 2] = ["dnp", "fitted"];
+
+fn header_label(key: &str) -> String {
+    match key {
+        "mpn" => "MPN".to_string(),
+        _ => uppercase_first_letter(key),
+    }
+}
+
 pub fn merge_key_list() -> Vec<String> {
     let mut keys: Vec<String> = Vec::new();
-    for i in STD_HEADERS {
+    for i in STD_HEADERS.iter().chain(PART_HEADERS.iter()) {
         keys.push(i.to_string());
     }
 
@@ -161,6 +451,95 @@ pub fn merge_key_list() -> Vec<String> {
 }
 
 impl Bom {
+    /// Empty BOM, items are merged by `merge_keys`.
+    pub fn new(merge_keys: &[String]) -> Bom {
+        Bom {
+            items: Vec::new(),
+            merge_keys: merge_keys.to_vec(),
+            variants: Vec::new(),
+        }
+    }
+
+    pub fn from_items<I: IntoIterator<Item = Item>>(items: I, merge_keys: &[String]) -> Bom {
+        let mut bom = Bom::new(merge_keys);
+        for i in items {
+            bom.push(i);
+        }
+        bom
+    }
+
+    /// Load a BOM from memory, eg. an upload or a PLM export.
+    pub fn from_reader<R: Read + Seek>(
+        reader: R,
+        format: BomFormat,
+        merge_keys: &[String],
+    ) -> Result<Bom> {
+        let mut bom = Bom::new(merge_keys);
+        bom.read(reader, format, "")?;
+        Ok(bom)
+    }
+
+    /// Append the rows read from `reader`, `name` is the source file name
+    /// recorded in each item.
+    pub fn read<R: Read + Seek>(&mut self, reader: R, format: BomFormat, name: &str) -> Result<()> {
+        let mut source = Source {
+            file: name.to_string(),
+            ..Default::default()
+        };
+        let (rows, headers) = match format {
+            BomFormat::Csv => csv_rows(
+                csv::ReaderBuilder::new()
+                    .has_headers(false)
+                    .from_reader(reader),
+            ),
+            BomFormat::Xlsx => {
+                let (sheet, rows, headers) = workbook_rows(&mut Xlsx::new(reader)?)?;
+                source.sheet = sheet;
+                (rows, headers)
+            }
+            BomFormat::Xls => {
+                let (sheet, rows, headers) = workbook_rows(&mut Xls::new(reader)?)?;
+                source.sheet = sheet;
+                (rows, headers)
+            }
+        };
+
+        let mut rng = Pcg32::seed_from_u64(self.items.len() as u64);
+        let mut items =
+            Bom::from_rows_and_headers(&rows, &headers, &source, &self.merge_keys, &mut rng)?;
+        self.items.append(&mut items);
+        Ok(())
+    }
+
+    /// Add an item, its category and unique id are computed from the merge keys.
+    pub fn push(&mut self, mut item: Item) {
+        let mut rng = Pcg32::seed_from_u64(self.items.len() as u64);
+        let item = item
+            .guess_category()
+            .generate_uuid(&self.merge_keys, &mut rng);
+        self.items.push(item);
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &Item> {
+        self.items.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn merge_keys(&self) -> &[String] {
+        &self.merge_keys
+    }
+
+    pub(crate) fn items_mut(&mut self) -> impl Iterator<Item = &mut Item> {
+        self.items.iter_mut()
+    }
+
     pub fn loader<P: AsRef<Path>>(path: &[P], merge_keys: &[String]) -> Bom {
         let mut it1: Vec<Item> = Vec::new();
         if let Ok(i) = Bom::from_csv(path, merge_keys) {
@@ -173,7 +552,183 @@ impl Bom {
         }
 
         it1.extend(it2);
-        Bom { items: it1 }
+        Bom {
+            items: it1,
+            merge_keys: merge_keys.to_vec(),
+            variants: Vec::new(),
+        }
+    }
+
+    pub fn add_variant(&mut self, variant: Variant) {
+        self.variants.retain(|v| v.name != variant.name);
+        self.variants.push(variant);
+    }
+
+    pub fn variants(&self) -> &[Variant] {
+        &self.variants
+    }
+
+    /// Apply the variant `name` to the items: unfitted designators become DNP,
+    /// substituted ones get the new fields. Rows are split when only some of
+    /// their designators change, so the result is ready to be merged.
+    pub fn variant(&self, name: &str) -> Result<Bom> {
+        let variant = match self.variants.iter().find(|v| v.name == name) {
+            Some(v) => v,
+            None => bail!("Unknown variant: {}", name),
+        };
+
+        let mut rng = Pcg32::seed_from_u64(name.len() as u64);
+        let mut items: Vec<Item> = Vec::new();
+        for item in self.items.iter() {
+            if item.designators().is_empty() {
+                items.push(item.clone());
+                continue;
+            }
+            let designators = item.designators().to_vec();
+
+            // Group designators that get the same overrides in this variant
+            let mut groups: Vec<(Overrides, Vec<String>)> = Vec::new();
+            for d in designators {
+                let ov = variant.overrides(&d);
+                match groups.iter_mut().find(|g| g.0 == ov) {
+                    Some(g) => g.1.push(d),
+                    None => groups.push((ov, vec![d])),
+                }
+            }
+
+            for (ov, designators) in groups {
+                let mut it = item.clone();
+                // Sources keep only what ends up in this line
+                for src in it.sources.iter_mut() {
+                    src.designators.retain(|d| designators.contains(d));
+                    src.quantity = src.designators.len();
+                }
+                it.fields
+                    .insert("designator".to_string(), Field::List(designators));
+                for (k, v) in ov.iter() {
+                    match Field::from_header_and_value(k, v) {
+                        Ok((hdr, value)) => {
+                            it.fields.insert(hdr, value);
+                        }
+                        Err(e) => warn!("Variant {}: {}, skip it", name, e),
+                    }
+                }
+                items.push(
+                    it.guess_category()
+                        .generate_uuid(&self.merge_keys, &mut rng),
+                );
+            }
+        }
+
+        Ok(Bom {
+            items,
+            merge_keys: self.merge_keys.clone(),
+            variants: self.variants.clone(),
+        })
+    }
+
+    /// Merge every variant and put them side by side: one quantity column per
+    /// variant, a line not populated in a variant has quantity 0 there.
+    /// Without variants this is the plain merged table.
+    pub fn variants_table(&self, sort: SortBy) -> Result<ItemsTable> {
+        if self.variants.is_empty() {
+            return Ok(self.merge().odered_vector_table_by(sort));
+        }
+
+        let mut tables: Vec<ItemsTable> = Vec::new();
+        for v in self.variants.iter() {
+            tables.push(self.variant(&v.name)?.merge().odered_vector_table_by(sort));
+        }
+
+        // Without merge keys the unique ids are random in every variant, the
+        // designators of a line are what it is in all of them
+        let key = |r: &ItemView| match self.merge_keys.is_empty() {
+            true => r.fields.get(1).cloned().unwrap_or_default(),
+            false => r.unique_id.clone(),
+        };
+
+        // A substitution can add a field in any variant
+        let mut headers: Vec<String> = Vec::new();
+        for t in tables.iter() {
+            for h in t.headers.iter().skip(1) {
+                if !headers.contains(h) {
+                    headers.push(h.clone());
+                }
+            }
+        }
+        let n = tables.len();
+        let spread = |table: &ItemsTable, row: &ItemView| {
+            let mut fields = vec!["0".to_string(); n];
+            fields.extend(vec![String::new(); headers.len()]);
+            for (h, f) in table.headers.iter().zip(row.fields.iter()).skip(1) {
+                if let Some(i) = headers.iter().position(|x| x == h) {
+                    fields[n + i] = f.clone();
+                }
+            }
+            fields
+        };
+
+        // The quantity of the first variant is the one per board
+        let mut combined = ItemsTable {
+            quantity: Some(0),
+            ..Default::default()
+        };
+        let mut keys: Vec<String> = Vec::new();
+        for v in self.variants.iter() {
+            combined.headers.push(format!("Quantity {}", v.name));
+        }
+        combined.headers.extend(headers.iter().cloned());
+
+        for (col, table) in tables.iter().enumerate() {
+            for row in table.rows.iter() {
+                let fields = spread(table, row);
+                let pos = keys.iter().position(|k| *k == key(row));
+                let view = match pos {
+                    Some(p) => &mut combined.rows[p],
+                    None => {
+                        keys.push(key(row));
+                        combined.rows.push(ItemView {
+                            fields: fields.clone(),
+                            ..row.clone()
+                        });
+                        combined.rows.last_mut().unwrap()
+                    }
+                };
+                view.fields[col] = row.fields[0].clone();
+                for (i, f) in fields.into_iter().enumerate().skip(n + 1) {
+                    if view.fields[i].is_empty() {
+                        view.fields[i] = f;
+                    }
+                }
+
+                // Designators of the line in all the variants
+                let mut dd: Vec<&str> = view.fields[n].split(", ").collect();
+                dd.extend(row.fields[1].split(", "));
+                dd.retain(|d| !d.is_empty());
+                dd.sort_by(|a, b| natural_cmp(a, b));
+                dd.dedup();
+                view.fields[n] = dd.join(", ");
+            }
+        }
+
+        // Only what is not populated in any variant is DNP for the whole set
+        for row in tables[0].dnp.iter() {
+            if tables
+                .iter()
+                .all(|t| t.dnp.iter().any(|r| key(r) == key(row)))
+            {
+                combined.dnp.push(ItemView {
+                    fields: spread(&tables[0], row),
+                    ..row.clone()
+                });
+            }
+        }
+
+        combined.rows.sort_by_key(|r| {
+            std::cmp::Reverse(Category::iter().find(|m| m.to_string() == r.category))
+        });
+
+        Ok(combined)
     }
 
     pub fn from_csv<P: AsRef<Path>>(path: &[P], merge_keys: &[String]) -> Result<Vec<Item>> {
@@ -190,7 +745,13 @@ impl Bom {
                 continue;
             }
             let (rows, headers) = csv_loader(i.as_ref());
-            if let Ok(mut ii) = Bom::from_rows_and_headers(&rows, &headers, merge_keys, &mut rng) {
+            let source = Source {
+                file: file_name(i.as_ref()),
+                ..Default::default()
+            };
+            if let Ok(mut ii) =
+                Bom::from_rows_and_headers(&rows, &headers, &source, merge_keys, &mut rng)
+            {
                 items.append(&mut ii);
             }
         }
@@ -210,8 +771,15 @@ impl Bom {
                 warn!("{:?} {:?} != xlsx xls: skip..", i.as_ref(), ext);
                 continue;
             }
-            let (rows, headers) = xlsx_loader(i);
-            if let Ok(mut ii) = Bom::from_rows_and_headers(&rows, &headers, merge_keys, &mut rng) {
+            let (sheet, rows, headers) = xlsx_loader(i);
+            let source = Source {
+                file: file_name(i.as_ref()),
+                sheet,
+                ..Default::default()
+            };
+            if let Ok(mut ii) =
+                Bom::from_rows_and_headers(&rows, &headers, &source, merge_keys, &mut rng)
+            {
                 items.append(&mut ii);
             }
         }
@@ -219,14 +787,22 @@ impl Bom {
     }
 
     fn from_rows_and_headers(
-        rows: &[Vec<String>],
+        rows: &[Row],
         headers: &HeaderMap,
+        source: &Source,
         merge_keys: &[String],
         seed: &mut Pcg32,
     ) -> Result<Vec<Item>> {
         let mut items: Vec<_> = Vec::new();
-        for row in rows.iter() {
-            if let Ok(item) = Self::parse_row(row, headers, merge_keys, seed) {
+        for (n, row) in rows.iter() {
+            if let Ok(mut item) = Self::parse_row(row, headers, merge_keys, seed) {
+                let designators = item.designators().to_vec();
+                item.sources.push(Source {
+                    row: *n,
+                    designators,
+                    quantity: item.quantity,
+                    ..source.clone()
+                });
                 items.push(item);
             }
         }
@@ -242,6 +818,11 @@ impl Bom {
         let mut items = Item::default();
         for (i, field) in row.iter().enumerate() {
             if let Some(h) = headers.get(&i) {
+                // Only a merged xlsx of ours has it, see `workbook_rows`
+                if h == "Quantity" {
+                    items.quantity = field.parse::<f64>().map_or(0, |q| q.max(0.0) as usize);
+                    continue;
+                }
                 if let Ok((hdr, value)) = Field::from_header_and_value(h, field) {
                     items.fields.entry(hdr.clone()).or_insert(value);
                 }
@@ -249,6 +830,7 @@ impl Bom {
                 warn!("Parse: No header {} for {}, skip it", i, field);
             };
         }
+        items.collect_alternates();
         Ok(items.guess_category().generate_uuid(merge_keys, seed))
     }
 
@@ -262,7 +844,7 @@ impl Bom {
         */
         let mut merged: HashMap<String, Item> = HashMap::new();
         for item in self.items.iter() {
-            print!("ID-> {:?}\n", item);
+            debug!("ID-> {:?}", item);
             if let Some(prev) = merged.get_mut(&item.unique_id) {
                 /*
                  * We found a row with same unique_id, so will go to merge.
@@ -272,17 +854,27 @@ impl Bom {
                     if let Some(Field::List(last_dd)) = item.fields.get("designator") {
                         dd.extend(last_dd.clone());
                     }
-                    dd.sort();
+                    dd.sort_by(|a, b| natural_cmp(a, b));
                     dd.dedup();
                     prev.quantity = dd.len();
                 }
+                prev.sources.extend(item.sources.iter().cloned());
+
+                // A different part on the same line is an approved alternate
+                let mut alternates = item.alternates.clone();
+                if let Some(part) = item.part() {
+                    alternates.push(part);
+                }
+                for alt in alternates {
+                    prev.add_alternate(alt);
+                }
 
                 /*
                  * Parse Filed vector, to merge columns
                  */
                 for c in item.fields.keys() {
                     // If in field we found a heder we skip it
-                    if STD_HEADERS.contains(&c.as_str()) {
+                    if STD_HEADERS.contains(&c.as_str()) || PART_HEADERS.contains(&c.as_str()) {
                         continue;
                     }
                     if let Some(Field::List(dd)) = prev.fields.get_mut(c) {
@@ -301,10 +893,26 @@ impl Bom {
         }
         Bom {
             items: merged.values().cloned().collect(),
+            merge_keys: self.merge_keys.clone(),
+            variants: self.variants.clone(),
         }
     }
 
     pub fn odered_vector_table(&mut self) -> ItemsTable {
+        self.odered_vector_table_by(SortBy::default())
+    }
+
+    /// Table sorted by category and then by `sort`, ties are broken by
+    /// designator so the order is the same on every run.
+    pub fn odered_vector_table_by(&mut self, sort: SortBy) -> ItemsTable {
+        self.items.sort_by(|a, b| {
+            b.category
+                .cmp(&a.category)
+                .then_with(|| sort.cmp(a, b))
+                .then_with(|| natural_cmp(&a.first_designator(), &b.first_designator()))
+                .then_with(|| a.unique_id.cmp(&b.unique_id))
+        });
+
         let mut headers: HashMap<String, usize> = HashMap::new();
         for (i, h) in STD_HEADERS.iter().enumerate() {
             info!("mappa->{} {}", i, h);
@@ -313,10 +921,21 @@ impl Bom {
 
         // Get header map and row max len
         let mut row_capacity: usize = headers.len();
+        for hdr in PART_HEADERS {
+            if self.items.iter().any(|i| i.fields.contains_key(hdr)) {
+                headers.insert(hdr.to_string(), row_capacity);
+                row_capacity += 1;
+            }
+        }
         for item in self.items.iter() {
-            for hdr in item.fields.iter() {
-                if !headers.contains_key(hdr.0) {
-                    headers.insert(hdr.0.clone(), row_capacity);
+            let mut names: Vec<&String> = item.fields.keys().collect();
+            names.sort();
+            for hdr in names {
+                if DNP_HEADERS.contains(&hdr.as_str()) {
+                    continue;
+                }
+                if !headers.contains_key(hdr) {
+                    headers.insert(hdr.clone(), row_capacity);
                     row_capacity += 1;
                 }
                 // info!(
@@ -326,18 +945,34 @@ impl Bom {
             }
         }
 
-        let mut items_table = ItemsTable::default();
-        self.items.sort_by(|a, b| b.category.cmp(&a.category));
+        // Quantity is the first of STD_HEADERS
+        let mut items_table = ItemsTable {
+            quantity: Some(0),
+            ..Default::default()
+        };
         let mut header_str = Vec::from_iter(headers.iter());
         header_str.sort_by(|a, b| a.1.cmp(b.1));
-        items_table.headers = header_str
+        items_table.headers = header_str.iter().map(|k| header_label(k.0)).collect();
+
+        // Approved alternates, a manufacturer and MPN column pair each
+        let alt_count = self
+            .items
             .iter()
-            .map(|k| uppercase_first_letter(k.0))
-            .collect();
+            .map(|i| i.alternates.len())
+            .max()
+            .unwrap_or(0);
+        for n in 1..=alt_count {
+            items_table.headers.push(format!("Alt Manufacturer {}", n));
+            items_table.headers.push(format!("Alt MPN {}", n));
+        }
 
         for item in self.items.iter() {
-            let mut m: Vec<String> = vec!["".to_string(); row_capacity];
+            let mut m: Vec<String> = vec!["".to_string(); row_capacity + 2 * alt_count];
             m[0] = format!("{}", item.quantity);
+            for (n, alt) in item.alternates.iter().enumerate() {
+                m[row_capacity + 2 * n] = alt.manufacturer.clone();
+                m[row_capacity + 2 * n + 1] = alt.mpn.clone();
+            }
 
             for k in item.fields.iter() {
                 if headers.contains_key(k.0) {
@@ -347,13 +982,19 @@ impl Bom {
                 }
             }
 
-            items_table.rows.push(ItemView {
+            let view = ItemView {
                 unique_id: item.unique_id.clone(),
                 category: format!("{}", item.category),
                 is_merged: item.is_merged,
                 is_np: item.is_np,
                 fields: m.clone(),
-            });
+                sources: item.sources.clone(),
+            };
+            if item.is_np {
+                items_table.dnp.push(view);
+            } else {
+                items_table.rows.push(view);
+            }
             debug!("{:?}", m);
         }
 
@@ -363,6 +1004,91 @@ impl Bom {
 
 pub type HeaderMap = HashMap<usize, String>;
 
+/// Input file formats understood by the loaders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum BomFormat {
+    Csv,
+    Xlsx,
+    Xls,
+}
+
+impl BomFormat {
+    /// Format from the file extension, None if it is not a BOM file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<BomFormat> {
+        match path
+            .as_ref()
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(|e| e.to_lowercase())
+            .as_deref()
+        {
+            Some("csv") => Some(BomFormat::Csv),
+            Some("xlsx") => Some(BomFormat::Xlsx),
+            Some("xls") => Some(BomFormat::Xls),
+            _ => None,
+        }
+    }
+}
+
+/// Order of the lines inside a category.
+#[derive(
+    Default, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, EnumString, EnumDisplay,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum SortBy {
+    /// First designator, with natural numeric order (R2 < R10)
+    #[default]
+    Designator,
+    /// Component value parsed from the comment (1k < 10k < 100k)
+    Value,
+    /// Bigger quantity first
+    Quantity,
+}
+
+impl SortBy {
+    fn cmp(&self, a: &Item, b: &Item) -> Ordering {
+        match self {
+            Self::Designator => natural_cmp(&a.first_designator(), &b.first_designator()),
+            Self::Value => {
+                let value = |i: &Item| i.comment().unwrap_or_default().to_string();
+                let (va, vb) = (value(a), value(b));
+                match (comment_to_number(&va), comment_to_number(&vb)) {
+                    (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => natural_cmp(&va, &vb),
+                }
+            }
+            Self::Quantity => b.quantity.cmp(&a.quantity),
+        }
+    }
+}
+
+/// Row number in the source file and its cells.
+type Row = (usize, Vec<String>);
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .and_then(OsStr::to_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Empty cell, xlsx ones are read as "-".
+fn is_blank(s: &str) -> bool {
+    matches!(s.trim(), "" | "-")
+}
+
+/// True if `name` is the name or the letter of a category label, eg.
+/// "Capacitors" or "C" for "** C Capacitors **".
+pub(crate) fn category_is(label: &str, name: &str) -> bool {
+    label
+        .split_whitespace()
+        .map(|w| w.trim_matches('*'))
+        .any(|w| !w.is_empty() && w.eq_ignore_ascii_case(name))
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct Item {
     quantity: usize,
@@ -371,15 +1097,269 @@ pub struct Item {
     is_np: bool,
     pub category: Category,
     fields: HashMap<String, Field>,
+    #[serde(default)]
+    sources: Vec<Source>,
+    #[serde(default)]
+    alternates: Vec<Alternate>,
 }
 
+/// Approved alternate part of a line: same function, another manufacturer.
+#[derive(Default, Deserialize, Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Alternate {
+    pub manufacturer: String,
+    pub mpn: String,
+}
+
+/// Fields with a typed getter, the others are extra fields.
+const TYPED_FIELDS: [&str; 9] = [
+    "designator",
+    "comment",
+    "footprint",
+    "description",
+    "layer",
+    "mounttechnology",
+    "manufacturer",
+    "mpn",
+    "quantity",
+];
+
 impl Item {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn quantity(&self) -> usize {
+        self.quantity
+    }
+
+    pub fn unique_id(&self) -> &str {
+        &self.unique_id
+    }
+
+    pub fn is_merged(&self) -> bool {
+        self.is_merged
+    }
+
+    pub fn is_np(&self) -> bool {
+        self.is_np
+    }
+
+    pub fn sources(&self) -> &[Source] {
+        &self.sources
+    }
+
+    pub fn designators(&self) -> &[String] {
+        match self.fields.get("designator") {
+            Some(Field::List(l)) => l,
+            _ => &[],
+        }
+    }
+
+    fn text(&self, name: &str) -> Option<&str> {
+        match self.fields.get(name) {
+            Some(Field::Item(s)) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        self.text("comment")
+    }
+
+    pub fn footprint(&self) -> Option<&str> {
+        self.text("footprint")
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.text("description")
+    }
+
+    pub fn manufacturer(&self) -> Option<&str> {
+        self.text("manufacturer")
+    }
+
+    pub fn mpn(&self) -> Option<&str> {
+        self.text("mpn")
+    }
+
+    /// Approved alternates, the main part excluded.
+    pub fn alternates(&self) -> &[Alternate] {
+        &self.alternates
+    }
+
+    pub fn layer(&self) -> Option<Layer> {
+        self.text("layer").and_then(|s| s.parse().ok())
+    }
+
+    pub fn mount_technology(&self) -> Option<MountTechnology> {
+        self.text("mounttechnology").and_then(|s| s.parse().ok())
+    }
+
+    /// Any field by name, standard or not.
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.get(&name.to_lowercase())
+    }
+
+    /// Fields without a typed getter, eg. "CODE xxx" and "NOTE xxx" columns.
+    pub fn extra_fields(&self) -> impl Iterator<Item = (&String, &Field)> {
+        self.fields.iter().filter(|(k, _)| {
+            !TYPED_FIELDS.contains(&k.as_str()) && !DNP_HEADERS.contains(&k.as_str())
+        })
+    }
+
+    /// Set the designators, quantity and category follow them.
+    pub fn with_designators<I, S>(mut self, designators: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let dd: Vec<String> = designators.into_iter().map(Into::into).collect();
+        self.quantity = dd.len();
+        self.fields
+            .insert("designator".to_string(), Field::List(dd));
+        self.guess_category()
+    }
+
+    fn with_text(mut self, name: &str, value: &str) -> Self {
+        self.fields
+            .insert(name.to_string(), Field::Item(value.to_string()));
+        self
+    }
+
+    pub fn with_comment(self, comment: &str) -> Self {
+        self.with_text("comment", comment)
+    }
+
+    pub fn with_footprint(self, footprint: &str) -> Self {
+        self.with_text("footprint", footprint)
+    }
+
+    pub fn with_description(self, description: &str) -> Self {
+        self.with_text("description", description)
+    }
+
+    pub fn with_manufacturer(self, manufacturer: &str) -> Self {
+        self.with_text("manufacturer", manufacturer)
+    }
+
+    pub fn with_mpn(self, mpn: &str) -> Self {
+        self.with_text("mpn", mpn)
+    }
+
+    pub fn with_alternate(mut self, manufacturer: &str, mpn: &str) -> Self {
+        self.add_alternate(Alternate {
+            manufacturer: manufacturer.to_string(),
+            mpn: mpn.to_string(),
+        });
+        self
+    }
+
+    pub fn with_layer(self, layer: Layer) -> Self {
+        self.with_text("layer", &layer.to_string())
+    }
+
+    pub fn with_mount_technology(self, mount: MountTechnology) -> Self {
+        self.with_text("mounttechnology", &mount.to_string())
+    }
+
+    /// Set a field as it would be read from a column named `name`: "CODE xxx"
+    /// and "NOTE xxx" become lists, unknown names are kept as plain text.
+    pub fn with_field(mut self, name: &str, value: &str) -> Self {
+        match Field::from_header_and_value(name, value) {
+            Ok((hdr, Field::List(l))) if hdr == "designator" => return self.with_designators(l),
+            Ok((hdr, field)) => {
+                self.fields.insert(hdr, field);
+            }
+            Err(_) => {
+                self.fields
+                    .insert(name.to_lowercase(), Field::Item(value.to_string()));
+            }
+        }
+        self
+    }
+
+    /// Set a field read from column `name` only if the item has none or it
+    /// is blank. Return true if the field was set.
+    pub(crate) fn fill_field(&mut self, name: &str, value: &str) -> bool {
+        let blank = self.field(name).is_none_or(|f| is_blank(&f.to_string()));
+        if !blank || is_blank(value) {
+            return false;
+        }
+        match Field::from_header_and_value(name, value) {
+            Ok((hdr, field)) => {
+                self.fields.insert(hdr, field);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Generate the unique id again after a change of the merge key fields.
+    /// Without merge keys the id is random and it is kept.
+    pub(crate) fn refresh_unique_id(&mut self, merge_keys: &[String]) {
+        if !merge_keys.is_empty() {
+            let mut rng = Pcg32::seed_from_u64(0);
+            self.guess_category();
+            self.generate_uuid(merge_keys, &mut rng);
+        }
+    }
+
+    /// Lowest designator in natural order, empty if there is none.
+    fn first_designator(&self) -> String {
+        self.designators()
+            .iter()
+            .min_by(|a, b| natural_cmp(a, b))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Main part as an alternate of another line, None without an MPN.
+    fn part(&self) -> Option<Alternate> {
+        let mpn = self.mpn().filter(|m| !is_blank(m))?;
+        Some(Alternate {
+            manufacturer: self.manufacturer().unwrap_or_default().to_string(),
+            mpn: mpn.to_string(),
+        })
+    }
+
+    fn add_alternate(&mut self, alt: Alternate) {
+        if is_blank(&alt.mpn)
+            || self.part().is_some_and(|p| p.mpn == alt.mpn)
+            || self.alternates.iter().any(|a| a.mpn == alt.mpn)
+        {
+            return;
+        }
+        self.alternates.push(alt);
+        self.alternates.sort();
+    }
+
+    /// Move the "manufacturer N" / "mpn N" fields read from the columns of the
+    /// alternates to `alternates`.
+    fn collect_alternates(&mut self) {
+        let mut numbers: Vec<String> = self
+            .fields
+            .keys()
+            .filter_map(|k| k.strip_prefix("mpn ").map(str::to_string))
+            .collect();
+        numbers.sort_by(|a, b| natural_cmp(a, b));
+        for n in numbers {
+            let mpn = self.fields.remove(&format!("mpn {}", n));
+            let manufacturer = self.fields.remove(&format!("manufacturer {}", n));
+            self.add_alternate(Alternate {
+                manufacturer: manufacturer.map(|f| f.to_string()).unwrap_or_default(),
+                mpn: mpn.map(|f| f.to_string()).unwrap_or_default(),
+            });
+        }
+        // A manufacturer without its MPN says nothing
+        self.fields.retain(|k, _| !k.starts_with("manufacturer "));
+    }
+
     pub fn guess_category(&mut self) -> Self {
-        self.category = match self.fields.get("designator") {
+        self.category = match self.designators().first() {
             Some(d) => {
                 match Regex::new(r"^([a-zA-Z_]{1,3})")
                     .unwrap()
-                    .captures(d.get_element_list().as_str())
+                    .captures(d.to_lowercase().as_str())
                 {
                     None => Category::Invalid,
                     Some(cc) => match String::from(cc.get(1).map_or("", |m| m.as_str()))
@@ -408,17 +1388,42 @@ impl Item {
         self.clone()
     }
 
+    /// A part is not populated when a dedicated DNP/Fitted column says so, or
+    /// when the comment or one of the merge keys starts with a DNP marker
+    /// ("NP", "DNP", "DNF", "Not Fitted", ...). A "no" in the DNP column, as
+    /// set by a fitted variant, overrides the markers.
+    fn detect_dnp(&self, merge_keys: &[String]) -> bool {
+        if let Some(d) = self.fields.get("dnp") {
+            let v = d.to_string().trim().to_lowercase();
+            match v.as_str() {
+                "" | "-" => (),
+                // An explicit "no", eg. a fitted variant, wins over the markers
+                "0" | "n" | "no" | "false" => return false,
+                _ => return true,
+            }
+        }
+        if let Some(d) = self.fields.get("fitted") {
+            let v = d.to_string().trim().to_lowercase();
+            if is_dnp_marker(&v) || matches!(v.as_str(), "0" | "n" | "no" | "false") {
+                return true;
+            }
+        }
+
+        std::iter::once(&String::from("comment"))
+            .chain(merge_keys.iter())
+            .filter_map(|k| self.fields.get(k))
+            .any(|d| is_dnp_marker(d.to_string().as_str()))
+    }
+
     fn generate_uuid(&mut self, merge_keys: &[String], seed: &mut Pcg32) -> Self {
         self.unique_id = "".to_string();
         self.is_merged = false;
         self.is_np = false;
 
-        // Update quantity counting the designator elements
-        if let Some(d) = self.fields.get("designator") {
-            self.quantity = match d {
-                Field::List(l) => l.len(),
-                _ => 0,
-            };
+        // Update quantity counting the designator elements, a line without
+        // designators keeps the quantity read with it
+        if self.fields.contains_key("designator") && !self.designators().is_empty() {
+            self.quantity = self.designators().len();
         };
 
         // No keys mergs, so get all items
@@ -433,15 +1438,10 @@ impl Item {
         let mut mm: Vec<String> = vec![];
         mm.push(format!("{}", self.category));
 
-        // Ckeck if line is NP
-        for item in merge_keys.iter() {
-            if let Some(d) = self.fields.get(item) {
-                // anyway the NP mark should not merge
-                if Regex::new("^NP ").unwrap().is_match(d.to_string().as_str()) {
-                    self.is_np = true;
-                    println!("np {:?}", d);
-                }
-            }
+        // Ckeck if line is NP, anyway the NP mark should not merge with fitted parts
+        self.is_np = self.detect_dnp(merge_keys);
+        if self.is_np {
+            mm.push(String::from("NP"));
         }
 
         for item in merge_keys.iter() {
@@ -478,14 +1478,14 @@ impl Item {
                     }
                     _ => (),
                 }
-                println!(">>>>>>>>>{}", field);
+                debug!(">>>>>>>>>{}", field);
                 mm.push(field);
             };
         }
 
         // generate_uuid
         self.unique_id = mm.join("-");
-        println!("unique ID -> {:} {:?}", self.unique_id, self.fields);
+        debug!("unique ID -> {:} {:?}", self.unique_id, self.fields);
         self.clone()
     }
 }
@@ -500,6 +1500,8 @@ impl Default for Item {
             is_np: false,
             category: Category::Invalid,
             fields,
+            sources: Vec::new(),
+            alternates: Vec::new(),
         }
     }
 }
@@ -570,6 +1572,64 @@ impl PartialOrd for Category {
     }
 }
 
+/// Board side of a part, parsed from the "Layer" column.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter)]
+pub enum Layer {
+    Top,
+    Bottom,
+}
+
+impl FromStr for Layer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "top" | "toplayer" | "top layer" | "t" | "front" | "f.cu" => Ok(Self::Top),
+            "bottom" | "bottomlayer" | "bottom layer" | "b" | "bot" | "back" | "b.cu" => {
+                Ok(Self::Bottom)
+            }
+            _ => bail!("Invalid layer: {}", s),
+        }
+    }
+}
+
+impl Display for Layer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Top => write!(f, "Top"),
+            Self::Bottom => write!(f, "Bottom"),
+        }
+    }
+}
+
+/// How a part is mounted, parsed from the "MountTechnology" column.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter)]
+pub enum MountTechnology {
+    Smd,
+    Tht,
+}
+
+impl FromStr for MountTechnology {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "smd" | "smt" | "surface mount" => Ok(Self::Smd),
+            "tht" | "th" | "through hole" | "thru hole" | "through-hole" => Ok(Self::Tht),
+            _ => bail!("Invalid mount technology: {}", s),
+        }
+    }
+}
+
+impl Display for MountTechnology {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Smd => write!(f, "SMD"),
+            Self::Tht => write!(f, "THT"),
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Field {
     List(Vec<String>),
@@ -588,33 +1648,34 @@ impl Display for Field {
 }
 
 impl Field {
-    fn get_element_list(&self) -> String {
-        match self {
-            Field::List(m) => {
-                if let Some(x) = m.first() {
-                    x.to_lowercase()
-                } else {
-                    "".to_string()
-                }
-            }
-            _ => "".to_string(),
-        }
-    }
-
     fn from_header_and_value(header: &str, value: &str) -> Result<(String, Field)> {
         let mut hdr = header.to_lowercase();
         let field: Field = match hdr.as_str() {
-            "designator" => Field::List(value.split(',').map(|m| m.trim().to_string()).collect()),
-            "comment" | "footprint" | "description" | "mounttechnology" | "layer" => {
-                Field::Item(value.to_string())
+            "designator" => Field::List(
+                value
+                    .split([',', '\n'])
+                    .map(|m| m.trim().to_string())
+                    .filter(|m| !m.is_empty())
+                    .collect(),
+            ),
+            "comment" | "footprint" | "description" | "mounttechnology" | "layer" | "dnp"
+            | "fitted" => Field::Item(value.to_string()),
+            other if part_header(other).is_some() => {
+                hdr = part_header(other).unwrap_or_default();
+                Field::Item(value.trim().to_string())
             }
             other => match Regex::new(r"(code|note)\s(.*)").unwrap().captures(other) {
                 Some(cc) => match cc.get(0) {
-                    Some(s) => {
-                        //debug!("{:?} > h{:?} -> {:?}", s, s.as_str().to_string(), value);
-                        hdr = s.as_str().to_string();
-                        Field::List(vec![value.to_string().to_uppercase()])
-                    }
+                    Some(s) => match Distributor::from_header(s.as_str()) {
+                        Some(d) => {
+                            hdr = d.field_name();
+                            Field::List(order_codes(value))
+                        }
+                        None => {
+                            hdr = s.as_str().to_string();
+                            Field::List(vec![value.to_string().to_uppercase()])
+                        }
+                    },
                     _ => Field::Invalid(value.to_string()),
                 },
                 _ => Field::Invalid(value.to_string()),
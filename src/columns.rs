@@ -0,0 +1,190 @@
+use anyhow::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::bom::{ItemView, ItemsTable};
+use super::project::load_config;
+
+/// Columns computed from the table rather than read from a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Computed {
+    /// Number of designators of the line
+    DesignatorCount,
+    /// The "Unit price" column of `add_costs`
+    UnitPrice,
+    /// The "Extended price" column of `add_costs`
+    ExtendedPrice,
+    Category,
+    UniqueId,
+    /// Files the line comes from
+    Sources,
+}
+
+impl Computed {
+    fn label(&self) -> &'static str {
+        match self {
+            Computed::DesignatorCount => "Designator count",
+            Computed::UnitPrice => "Unit price",
+            Computed::ExtendedPrice => "Extended price",
+            Computed::Category => "Category",
+            Computed::UniqueId => "Unique id",
+            Computed::Sources => "Sources",
+        }
+    }
+}
+
+/// An output column: a field of the table, by header and ignoring case, or
+/// a computed value, under `label` or the header.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Column {
+    #[serde(default)]
+    pub field: String,
+    #[serde(default)]
+    pub computed: Option<Computed>,
+    #[serde(default)]
+    pub label: String,
+}
+
+impl Column {
+    pub fn field(field: &str) -> Column {
+        Column {
+            field: field.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn computed(computed: Computed) -> Column {
+        Column {
+            computed: Some(computed),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_label(mut self, label: &str) -> Column {
+        self.label = label.to_string();
+        self
+    }
+}
+
+/// Which columns the outputs have, in order. An empty spec keeps the table
+/// as it is. The quantity can be moved and relabelled, the table keeps
+/// track of its column.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ColumnSpec {
+    #[serde(default)]
+    pub columns: Vec<Column>,
+}
+
+/// Where the value of a column comes from in the table.
+enum Getter {
+    Field(usize),
+    Computed(Computed),
+    Missing,
+}
+
+impl Getter {
+    fn get(&self, row: &ItemView, designator: Option<usize>) -> String {
+        match self {
+            Getter::Field(i) => row.fields.get(*i).cloned().unwrap_or_default(),
+            Getter::Computed(Computed::DesignatorCount) => designator
+                .and_then(|i| row.fields.get(i))
+                .map(|d| d.split(", ").filter(|d| !d.trim().is_empty()).count())
+                .unwrap_or(0)
+                .to_string(),
+            Getter::Computed(Computed::Category) => row.category.clone(),
+            Getter::Computed(Computed::UniqueId) => row.unique_id.clone(),
+            Getter::Computed(Computed::Sources) => {
+                let mut files: Vec<&str> = Vec::new();
+                for s in row.sources.iter() {
+                    if !files.contains(&s.file.as_str()) {
+                        files.push(&s.file);
+                    }
+                }
+                files.join(", ")
+            }
+            // Prices are resolved to a field
+            Getter::Computed(_) | Getter::Missing => String::new(),
+        }
+    }
+}
+
+impl ColumnSpec {
+    pub fn new(columns: Vec<Column>) -> ColumnSpec {
+        ColumnSpec { columns }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Load a spec from json or toml, by extension:
+    /// `[[columns]] field = "Designator" label = "Refs"`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ColumnSpec> {
+        load_config(path, "column specs")
+    }
+}
+
+impl ItemsTable {
+    /// Keep, reorder and rename the columns as `spec` says, on the populated
+    /// and the not populated rows. A field missing from the table gives an
+    /// empty column. Call it last, the other steps find columns by header.
+    pub fn apply_columns(&mut self, spec: &ColumnSpec) {
+        if spec.is_empty() {
+            return;
+        }
+        let find = |name: &str| {
+            self.headers
+                .iter()
+                .position(|h| h.eq_ignore_ascii_case(name))
+        };
+        let find_prefix = |prefix: &str| {
+            self.headers
+                .iter()
+                .position(|h| h.to_lowercase().starts_with(prefix))
+        };
+        let designator = find("designator");
+
+        let mut headers = Vec::new();
+        let mut getters = Vec::new();
+        let mut quantity = None;
+        for c in spec.columns.iter() {
+            let (getter, header) = match c.computed {
+                Some(Computed::UnitPrice) => match find_prefix("unit price") {
+                    Some(i) => (Getter::Field(i), self.headers[i].clone()),
+                    None => (Getter::Missing, Computed::UnitPrice.label().to_string()),
+                },
+                Some(Computed::ExtendedPrice) => match find_prefix("extended price") {
+                    Some(i) => (Getter::Field(i), self.headers[i].clone()),
+                    None => (Getter::Missing, Computed::ExtendedPrice.label().to_string()),
+                },
+                Some(computed) => (Getter::Computed(computed), computed.label().to_string()),
+                None => match find(&c.field) {
+                    Some(i) => (Getter::Field(i), self.headers[i].clone()),
+                    None => {
+                        warn!("No column {} in the table", c.field);
+                        (Getter::Missing, c.field.clone())
+                    }
+                },
+            };
+            if let Getter::Field(i) = getter {
+                if quantity.is_none() && Some(i) == self.quantity {
+                    quantity = Some(headers.len());
+                }
+            }
+            headers.push(if c.label.is_empty() {
+                header
+            } else {
+                c.label.clone()
+            });
+            getters.push(getter);
+        }
+
+        for row in self.rows.iter_mut().chain(self.dnp.iter_mut()) {
+            row.fields = getters.iter().map(|g| g.get(row, designator)).collect();
+        }
+        self.headers = headers;
+        self.quantity = quantity;
+    }
+}
@@ -0,0 +1,286 @@
+use anyhow::{bail, Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::bom::ItemsTable;
+use super::utils::read_sheet_rows;
+
+/// Unit price from `quantity` pieces on.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct PriceBreak {
+    pub quantity: usize,
+    pub price: f64,
+}
+
+/// Price breaks keyed by MPN or supplier order code, all in one currency.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PriceList {
+    currency: String,
+    prices: HashMap<String, Vec<PriceBreak>>,
+}
+
+/// Keys are compared uppercase and without spaces, like the order codes.
+fn price_key(key: &str) -> String {
+    key.chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+/// Price as written in a spreadsheet: "0.12", "0,12", "€ 1,234.50" or
+/// "1.234,50". With both separators the last one is the decimal point, a
+/// lone comma before three digits ("1,234") groups thousands.
+fn parse_price(value: &str) -> Option<f64> {
+    let s: String = value
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.' || *c == ',')
+        .collect();
+    let decimal = match (s.rfind('.'), s.rfind(',')) {
+        (Some(d), Some(c)) => Some(d.max(c)),
+        (Some(d), None) if s.matches('.').count() == 1 => Some(d),
+        (None, Some(c)) if s.matches(',').count() == 1 => {
+            let thousands = s.len() - c == 4 && !s.starts_with('0') && c > 0;
+            (!thousands).then_some(c)
+        }
+        _ => None,
+    };
+    let s: String = s
+        .char_indices()
+        .filter_map(|(i, c)| match c {
+            '.' | ',' if Some(i) == decimal => Some('.'),
+            '.' | ',' => None,
+            c => Some(c),
+        })
+        .collect();
+    s.parse().ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum PriceColumn {
+    Key,
+    Quantity,
+    Price,
+    Currency,
+}
+
+fn price_column(header: &str) -> Option<PriceColumn> {
+    match header.trim().to_lowercase().as_str() {
+        "mpn" | "manufacturer part number" | "part number" | "order code" | "code" | "sku" => {
+            Some(PriceColumn::Key)
+        }
+        "quantity" | "qty" | "break" | "price break" | "min qty" => Some(PriceColumn::Quantity),
+        "price" | "unit price" => Some(PriceColumn::Price),
+        "currency" => Some(PriceColumn::Currency),
+        _ => None,
+    }
+}
+
+impl PriceList {
+    pub fn new(currency: &str) -> PriceList {
+        PriceList {
+            currency: currency.to_uppercase(),
+            prices: HashMap::new(),
+        }
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    pub fn len(&self) -> usize {
+        self.prices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prices.is_empty()
+    }
+
+    pub fn add(&mut self, key: &str, quantity: usize, price: f64) {
+        let breaks = self.prices.entry(price_key(key)).or_default();
+        breaks.retain(|b| b.quantity != quantity);
+        breaks.push(PriceBreak { quantity, price });
+        breaks.sort_by_key(|b| b.quantity);
+    }
+
+    /// Unit price of `key` buying `quantity` pieces: the biggest break not
+    /// above it, or the first one when `quantity` is under all the breaks.
+    pub fn unit_price(&self, key: &str, quantity: usize) -> Option<f64> {
+        let breaks = self.prices.get(&price_key(key))?;
+        breaks
+            .iter()
+            .rev()
+            .find(|b| b.quantity <= quantity)
+            .or_else(|| breaks.first())
+            .map(|b| b.price)
+    }
+
+    /// Load a csv or xlsx price table, one row per price break. Columns are
+    /// found by name: "MPN" or "Order code" as key, "Quantity", "Price" and
+    /// an optional "Currency", the same for every row.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PriceList> {
+        let path = path.as_ref();
+        let rows = read_sheet_rows(path)?;
+        PriceList::from_rows(&rows).with_context(|| format!("{}", path.display()))
+    }
+
+    /// Price list from spreadsheet rows, everything before the header row is
+    /// skipped.
+    pub fn from_rows(rows: &[Vec<String>]) -> Result<PriceList> {
+        let mut columns: HashMap<PriceColumn, usize> = HashMap::new();
+        let mut list = PriceList::default();
+        let mut currency: Option<String> = None;
+
+        for (n, row) in rows.iter().enumerate() {
+            if columns.is_empty() {
+                for (i, c) in row.iter().enumerate() {
+                    if let Some(col) = price_column(c) {
+                        columns.entry(col).or_insert(i);
+                    }
+                }
+                if !columns.contains_key(&PriceColumn::Key)
+                    || !columns.contains_key(&PriceColumn::Price)
+                {
+                    columns.clear();
+                }
+                continue;
+            }
+
+            let cell = |col| {
+                columns
+                    .get(&col)
+                    .and_then(|i| row.get(*i))
+                    .map_or("", |s| s.trim())
+            };
+            let key = cell(PriceColumn::Key);
+            if key.is_empty() {
+                continue;
+            }
+            let price = match parse_price(cell(PriceColumn::Price)) {
+                Some(p) => p,
+                None => {
+                    warn!("Price list row {}: invalid price for {}, skip", n + 1, key);
+                    continue;
+                }
+            };
+            let quantity = cell(PriceColumn::Quantity).parse().unwrap_or(1);
+
+            let row_currency = cell(PriceColumn::Currency).to_uppercase();
+            match &currency {
+                Some(c) if *c != row_currency => bail!(
+                    "Row {}: currency {} in a {} price list",
+                    n + 1,
+                    row_currency,
+                    c
+                ),
+                Some(_) => {}
+                None => currency = Some(row_currency),
+            }
+            list.add(key, quantity, price);
+        }
+
+        if columns.is_empty() {
+            bail!("No price list header: a key (MPN, Order code) and a Price column are needed");
+        }
+        list.currency = currency.unwrap_or_default();
+        Ok(list)
+    }
+}
+
+/// Extended price of the lines of one category.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CategoryCost {
+    pub category: String,
+    pub total: f64,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CostSummary {
+    pub currency: String,
+    pub build_quantity: usize,
+    pub categories: Vec<CategoryCost>,
+    pub total: f64,
+    /// Unique id of the lines without a price, not in the totals.
+    pub unpriced: Vec<String>,
+}
+
+impl ItemsTable {
+    /// Price the lines to build `build_quantity` boards: append a unit price
+    /// and an extended price column and fill `cost`. A line is looked up by
+    /// its MPN first and then by its supplier codes; the quantity is the gross
+    /// one of `add_attrition` or the first column, so the first variant for a
+    /// variants table.
+    pub fn add_costs(&mut self, prices: &PriceList, build_quantity: usize) {
+        let keys: Vec<usize> = self
+            .headers
+            .iter()
+            .enumerate()
+            .filter(|(_, h)| {
+                let h = h.to_lowercase();
+                h == "mpn" || h.starts_with("code ")
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut summary = CostSummary {
+            currency: prices.currency().to_string(),
+            build_quantity,
+            ..Default::default()
+        };
+        let quantities = self.purchase_quantities(build_quantity);
+        for (row, quantity) in self.rows.iter_mut().zip(quantities) {
+            let unit = keys
+                .iter()
+                .filter_map(|i| row.fields.get(*i))
+                .flat_map(|cell| cell.split(", "))
+                .filter(|k| !k.trim().is_empty())
+                .find_map(|k| prices.unit_price(k, quantity));
+
+            match unit {
+                Some(unit) => {
+                    let extended = unit * quantity as f64;
+                    row.fields.push(format!("{:.4}", unit));
+                    row.fields.push(format!("{:.2}", extended));
+                    // Categories in order of first appearance, like the writers
+                    let category = summary
+                        .categories
+                        .iter_mut()
+                        .find(|c| c.category == row.category);
+                    match category {
+                        Some(c) => c.total += extended,
+                        _ => summary.categories.push(CategoryCost {
+                            category: row.category.clone(),
+                            total: extended,
+                        }),
+                    }
+                    summary.total += extended;
+                }
+                None => {
+                    row.fields.push(String::new());
+                    row.fields.push(String::new());
+                    summary.unpriced.push(row.unique_id.clone());
+                }
+            }
+        }
+        for row in self.dnp.iter_mut() {
+            row.fields.push(String::new());
+            row.fields.push(String::new());
+        }
+
+        let currency = match prices.currency() {
+            "" => String::new(),
+            c => format!(" ({})", c),
+        };
+        self.headers.push(format!("Unit price{}", currency));
+        self.headers.push(format!("Extended price{}", currency));
+        self.cost = Some(summary);
+    }
+
+    /// Column of the extended prices, wherever `apply_columns` put it.
+    pub fn extended_price_column(&self) -> Option<usize> {
+        self.headers
+            .iter()
+            .position(|h| h.to_lowercase().starts_with("extended price"))
+    }
+}
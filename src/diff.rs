@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+use super::bom::{Bom, ItemView, ItemsTable};
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FieldChange {
+    pub header: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// A line in both revisions, `item` is the new one.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LineChange {
+    pub item: ItemView,
+    pub old_quantity: String,
+    pub fields: Vec<FieldChange>,
+}
+
+impl LineChange {
+    pub fn new_quantity(&self) -> &str {
+        self.item.fields.first().map_or("", |s| s.as_str())
+    }
+}
+
+/// A designator that belongs to another merged line in the new revision.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DesignatorMove {
+    pub designator: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BomDiff {
+    pub headers: Vec<String>,
+    pub added: Vec<ItemView>,
+    pub removed: Vec<ItemView>,
+    pub changed: Vec<LineChange>,
+    pub moved: Vec<DesignatorMove>,
+}
+
+impl BomDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.moved.is_empty()
+    }
+}
+
+fn all_rows(table: &ItemsTable) -> Vec<&ItemView> {
+    table.rows.iter().chain(table.dnp.iter()).collect()
+}
+
+/// Field value of `row` for the column named `header` in `headers`.
+fn field<'a>(headers: &[String], row: &'a ItemView, header: &str) -> &'a str {
+    headers
+        .iter()
+        .position(|h| h == header)
+        .and_then(|i| row.fields.get(i))
+        .map_or("", |s| s.as_str())
+}
+
+/// Copy of `row` with the fields ordered as `to` instead of `from`.
+fn remap(from: &[String], row: &ItemView, to: &[String]) -> ItemView {
+    ItemView {
+        fields: to.iter().map(|h| field(from, row, h).to_string()).collect(),
+        ..row.clone()
+    }
+}
+
+fn designators<'a>(headers: &[String], row: &'a ItemView) -> Vec<&'a str> {
+    field(headers, row, "Designator")
+        .split(", ")
+        .filter(|d| !d.is_empty())
+        .collect()
+}
+
+impl Bom {
+    /// Compare the merged items of two revisions by merge key: `self` is the
+    /// old revision, `other` the new one. Without merge keys every row gets a
+    /// random unique id, so the diff makes sense only when both are loaded
+    /// with the same, non empty, merge keys.
+    pub fn diff(&self, other: &Bom) -> BomDiff {
+        let old = self.merge().odered_vector_table();
+        let new = other.merge().odered_vector_table();
+
+        let mut diff = BomDiff {
+            headers: old.headers.clone(),
+            ..Default::default()
+        };
+        for h in new.headers.iter() {
+            if !diff.headers.contains(h) {
+                diff.headers.push(h.clone());
+            }
+        }
+
+        let old_rows = all_rows(&old);
+        let new_rows = all_rows(&new);
+        let old_by_id: HashMap<&str, &ItemView> = old_rows
+            .iter()
+            .map(|r| (r.unique_id.as_str(), *r))
+            .collect();
+        let new_by_id: HashMap<&str, &ItemView> = new_rows
+            .iter()
+            .map(|r| (r.unique_id.as_str(), *r))
+            .collect();
+
+        for row in old_rows.iter() {
+            if !new_by_id.contains_key(row.unique_id.as_str()) {
+                diff.removed.push(remap(&old.headers, row, &diff.headers));
+            }
+        }
+
+        for row in new_rows.iter() {
+            let prev = match old_by_id.get(row.unique_id.as_str()) {
+                Some(prev) => prev,
+                None => {
+                    diff.added.push(remap(&new.headers, row, &diff.headers));
+                    continue;
+                }
+            };
+
+            let mut change = LineChange {
+                item: remap(&new.headers, row, &diff.headers),
+                old_quantity: field(&old.headers, prev, "Quantity").to_string(),
+                fields: Vec::new(),
+            };
+            for h in diff.headers.iter().filter(|h| *h != "Quantity") {
+                let a = field(&old.headers, prev, h);
+                let b = field(&new.headers, row, h);
+                if a != b {
+                    change.fields.push(FieldChange {
+                        header: h.clone(),
+                        old: a.to_string(),
+                        new: b.to_string(),
+                    });
+                }
+            }
+            if change.old_quantity != change.new_quantity() || !change.fields.is_empty() {
+                diff.changed.push(change);
+            }
+        }
+
+        // Designators that still exist but on another line
+        let mut old_line: HashMap<&str, &str> = HashMap::new();
+        for row in old_rows.iter() {
+            for d in designators(&old.headers, row) {
+                old_line.insert(d, row.unique_id.as_str());
+            }
+        }
+        for row in new_rows.iter() {
+            for d in designators(&new.headers, row) {
+                if let Some(from) = old_line.get(d) {
+                    if *from != row.unique_id {
+                        diff.moved.push(DesignatorMove {
+                            designator: d.to_string(),
+                            from: from.to_string(),
+                            to: row.unique_id.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        diff.added.sort_by(|a, b| a.unique_id.cmp(&b.unique_id));
+        diff.removed.sort_by(|a, b| a.unique_id.cmp(&b.unique_id));
+        diff.changed
+            .sort_by(|a, b| a.item.unique_id.cmp(&b.item.unique_id));
+        diff.moved.sort_by(|a, b| a.designator.cmp(&b.designator));
+
+        diff
+    }
+}
+
+impl Display for BomDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for r in self.removed.iter() {
+            writeln!(f, "- {}: {}", r.unique_id, r.fields.join(";"))?;
+        }
+        for r in self.added.iter() {
+            writeln!(f, "+ {}: {}", r.unique_id, r.fields.join(";"))?;
+        }
+        for c in self.changed.iter() {
+            writeln!(f, "~ {}", c.item.unique_id)?;
+            if c.old_quantity != c.new_quantity() {
+                writeln!(
+                    f,
+                    "    Quantity: {} -> {}",
+                    c.old_quantity,
+                    c.new_quantity()
+                )?;
+            }
+            for fc in c.fields.iter() {
+                writeln!(f, "    {}: {} -> {}", fc.header, fc.old, fc.new)?;
+            }
+        }
+        for m in self.moved.iter() {
+            writeln!(f, "> {}: {} -> {}", m.designator, m.from, m.to)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use log::warn;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::io::Write;
+use strum::IntoEnumIterator;
+use strum_macros::{Display as EnumDisplay, EnumIter, EnumString};
+
+use super::bom::{Bom, Field, Item};
+
+/// Distributors whose "CODE xxx" columns are order codes we know how to check.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Deserialize,
+    Serialize,
+    EnumString,
+    EnumDisplay,
+    EnumIter,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Distributor {
+    Farnell,
+    Mouser,
+    Digikey,
+}
+
+impl Distributor {
+    /// Distributor of a column like "CODE Farnell" or "code digi-key".
+    pub fn from_header(header: &str) -> Option<Distributor> {
+        let h = header.to_lowercase();
+        let name: String = h
+            .trim()
+            .strip_prefix("code")?
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect();
+        match name.as_str() {
+            "farnell" | "element14" => Some(Distributor::Farnell),
+            "mouser" => Some(Distributor::Mouser),
+            "digikey" | "dk" => Some(Distributor::Digikey),
+            _ => None,
+        }
+    }
+
+    /// Item field holding the order codes of this distributor.
+    pub fn field_name(&self) -> String {
+        format!("code {}", self)
+    }
+
+    /// Check the format of a normalized order code.
+    pub fn is_valid(&self, code: &str) -> bool {
+        lazy_static! {
+            // 7 digits, "RL" for cut reels
+            static ref FARNELL: Regex = Regex::new(r"^\d{6,8}(RL)?$").unwrap();
+            // Manufacturer prefix, then the manufacturer part number
+            static ref MOUSER: Regex = Regex::new(r"^\d{2,3}-[A-Z0-9][A-Z0-9.\-/+#]*$").unwrap();
+            static ref DIGIKEY: Regex = Regex::new(r"^[A-Z0-9][A-Z0-9.\-/+#]*-ND$").unwrap();
+        }
+        match self {
+            Self::Farnell => FARNELL.is_match(code),
+            Self::Mouser => MOUSER.is_match(code),
+            Self::Digikey => DIGIKEY.is_match(code),
+        }
+    }
+
+    /// Columns of the cart upload file.
+    fn cart_headers(&self) -> [&'static str; 3] {
+        match self {
+            Self::Farnell => ["Order Code", "Quantity", "Line Note"],
+            Self::Mouser => ["Mouser Part Number", "Quantity", "Customer Part Number"],
+            Self::Digikey => ["Digi-Key Part Number", "Quantity", "Customer Reference"],
+        }
+    }
+}
+
+/// Order codes of a cell: several codes may be split by comma or semicolon,
+/// spaces are dropped and letters uppercased.
+pub(crate) fn order_codes(value: &str) -> Vec<String> {
+    let mut codes: Vec<String> = value
+        .split([',', ';'])
+        .map(|c| {
+            c.chars()
+                .filter(|c| !c.is_whitespace())
+                .collect::<String>()
+                .to_uppercase()
+        })
+        .filter(|c| !c.is_empty() && c != "-")
+        .collect();
+    codes.sort();
+    codes.dedup();
+    codes
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum OrderCodeIssueKind {
+    /// The code does not look like one of the distributor
+    Invalid,
+    /// Rows merged in the same line carry different codes
+    Conflict,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct OrderCodeIssue {
+    pub unique_id: String,
+    pub designators: Vec<String>,
+    pub distributor: Distributor,
+    pub codes: Vec<String>,
+    pub kind: OrderCodeIssueKind,
+}
+
+impl Display for OrderCodeIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let what = match self.kind {
+            OrderCodeIssueKind::Invalid => "invalid",
+            OrderCodeIssueKind::Conflict => "conflicting",
+        };
+        write!(
+            f,
+            "{} [{}]: {} {} codes {}",
+            self.unique_id,
+            self.designators.join(", "),
+            what,
+            self.distributor,
+            self.codes.join(", ")
+        )
+    }
+}
+
+impl Item {
+    /// Order codes of `distributor`, more than one after a merge is a conflict.
+    pub fn order_codes(&self, distributor: Distributor) -> &[String] {
+        match self.field(&distributor.field_name()) {
+            Some(Field::List(l)) => l,
+            _ => &[],
+        }
+    }
+}
+
+impl Bom {
+    /// Invalid and conflicting order codes of the populated lines, call it on
+    /// the merged BOM to find conflicts between rows.
+    pub fn order_code_issues(&self) -> Vec<OrderCodeIssue> {
+        let mut issues = Vec::new();
+        for item in self.items().filter(|i| !i.is_np()) {
+            for d in Distributor::iter() {
+                let codes = item.order_codes(d);
+                let issue = |kind, codes: Vec<String>| OrderCodeIssue {
+                    unique_id: item.unique_id().to_string(),
+                    designators: item.designators().to_vec(),
+                    distributor: d,
+                    codes,
+                    kind,
+                };
+                let invalid: Vec<String> =
+                    codes.iter().filter(|c| !d.is_valid(c)).cloned().collect();
+                if !invalid.is_empty() {
+                    issues.push(issue(OrderCodeIssueKind::Invalid, invalid));
+                }
+                if codes.len() > 1 {
+                    issues.push(issue(OrderCodeIssueKind::Conflict, codes.to_vec()));
+                }
+            }
+        }
+        issues.sort_by(|a, b| a.unique_id.cmp(&b.unique_id));
+        issues
+    }
+
+    /// Write the cart upload csv of `distributor`, one line per populated item
+    /// with a valid code. Lines with invalid or conflicting codes are skipped,
+    /// see `order_code_issues`. Returns the number of lines written.
+    pub fn write_cart<W: Write>(&self, distributor: Distributor, writer: W) -> Result<usize> {
+        let mut items: Vec<&Item> = self.items().filter(|i| !i.is_np()).collect();
+        items.sort_by(|a, b| a.unique_id().cmp(b.unique_id()));
+
+        let mut wr = csv::Writer::from_writer(writer);
+        wr.write_record(distributor.cart_headers())?;
+        let mut lines = 0;
+        for item in items {
+            let code = match item.order_codes(distributor) {
+                [] => continue,
+                [code] if distributor.is_valid(code) => code,
+                codes => {
+                    warn!(
+                        "{}: skip {} codes {}",
+                        item.unique_id(),
+                        distributor,
+                        codes.join(", ")
+                    );
+                    continue;
+                }
+            };
+            wr.write_record([
+                code.as_str(),
+                &item.quantity().to_string(),
+                &item.designators().join(" "),
+            ])?;
+            lines += 1;
+        }
+        wr.flush()?;
+        Ok(lines)
+    }
+}
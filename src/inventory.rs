@@ -0,0 +1,252 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::attrition::AttritionRules;
+use super::bom::{Bom, Item};
+use super::library::{code_key, value_key, INTERNAL_CODE};
+use super::utils::{natural_cmp, read_sheet_rows};
+
+/// Parts on hand in the warehouse under one code.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StockEntry {
+    /// Internal code or MPN
+    pub code: String,
+    /// Where the parts are, several locations are joined by ", "
+    pub location: String,
+    pub quantity: u64,
+    #[serde(default)]
+    pub value: String,
+    #[serde(default)]
+    pub footprint: String,
+}
+
+/// The lookup indices are not saved, they are rebuilt from the entries.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "InventoryEntries")]
+pub struct Inventory {
+    entries: Vec<StockEntry>,
+    #[serde(skip)]
+    by_code: HashMap<String, usize>,
+    #[serde(skip)]
+    by_value: HashMap<String, usize>,
+}
+
+#[derive(Deserialize)]
+struct InventoryEntries {
+    entries: Vec<StockEntry>,
+}
+
+impl From<InventoryEntries> for Inventory {
+    fn from(data: InventoryEntries) -> Inventory {
+        let mut inv = Inventory::new();
+        for e in data.entries {
+            inv.add(e);
+        }
+        inv
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum StockColumn {
+    Code,
+    Location,
+    Quantity,
+    Value,
+    Footprint,
+}
+
+fn stock_column(header: &str) -> Option<StockColumn> {
+    match header.trim().to_lowercase().as_str() {
+        "code" | "internal code" | "article" | "mpn" | "part number" => Some(StockColumn::Code),
+        "location" | "bin" | "position" => Some(StockColumn::Location),
+        "quantity" | "qty" | "on hand" | "stock" => Some(StockColumn::Quantity),
+        "value" | "comment" => Some(StockColumn::Value),
+        "footprint" | "package" => Some(StockColumn::Footprint),
+        _ => None,
+    }
+}
+
+impl Inventory {
+    pub fn new() -> Inventory {
+        Inventory::default()
+    }
+
+    pub fn entries(&self) -> &[StockEntry] {
+        &self.entries
+    }
+
+    /// Add parts on hand, an entry with the same code gets more quantity
+    /// and the location if new.
+    pub fn add(&mut self, entry: StockEntry) {
+        let key = code_key(&entry.code);
+        if let Some(i) = self.by_code.get(&key) {
+            let e = &mut self.entries[*i];
+            e.quantity += entry.quantity;
+            if !entry.location.is_empty() && !e.location.split(", ").any(|l| l == entry.location) {
+                if !e.location.is_empty() {
+                    e.location.push_str(", ");
+                }
+                e.location.push_str(&entry.location);
+            }
+            return;
+        }
+        self.by_code.insert(key, self.entries.len());
+        if let Some(k) = value_key(&entry.value, &entry.footprint) {
+            self.by_value.entry(k).or_insert(self.entries.len());
+        }
+        self.entries.push(entry);
+    }
+
+    /// Load the warehouse csv or xlsx: "Code", "Location" and "Quantity"
+    /// columns, "Value" and "Footprint" to match parts without a code.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Inventory> {
+        let path = path.as_ref();
+        let rows = read_sheet_rows(path)?;
+        Inventory::from_rows(&rows).with_context(|| format!("{}", path.display()))
+    }
+
+    /// Inventory from spreadsheet rows, everything before the header row is
+    /// skipped.
+    pub fn from_rows(rows: &[Vec<String>]) -> Result<Inventory> {
+        let mut columns: HashMap<StockColumn, usize> = HashMap::new();
+        let mut inv = Inventory::new();
+        for row in rows.iter() {
+            if columns.is_empty() {
+                for (i, c) in row.iter().enumerate() {
+                    if let Some(col) = stock_column(c) {
+                        columns.entry(col).or_insert(i);
+                    }
+                }
+                if !columns.contains_key(&StockColumn::Code)
+                    || !columns.contains_key(&StockColumn::Quantity)
+                {
+                    columns.clear();
+                }
+                continue;
+            }
+
+            let cell = |col| {
+                columns
+                    .get(&col)
+                    .and_then(|i| row.get(*i))
+                    .map_or(String::new(), |s| s.trim().to_string())
+            };
+            let code = cell(StockColumn::Code);
+            if code.is_empty() {
+                continue;
+            }
+            inv.add(StockEntry {
+                code,
+                location: cell(StockColumn::Location),
+                quantity: cell(StockColumn::Quantity)
+                    .parse::<f64>()
+                    .map_or(0, |q| q.max(0.0) as u64),
+                value: cell(StockColumn::Value),
+                footprint: cell(StockColumn::Footprint),
+            });
+        }
+        if columns.is_empty() {
+            bail!("No inventory header: a Code and a Quantity column are needed");
+        }
+        Ok(inv)
+    }
+
+    /// Entry of `item`, by internal code, then MPN, then value and footprint.
+    fn find(&self, item: &Item) -> Option<usize> {
+        let code = item
+            .field(INTERNAL_CODE)
+            .map(|f| f.to_string())
+            .and_then(|c| self.by_code.get(&code_key(&c)));
+        let mpn = || item.mpn().and_then(|m| self.by_code.get(&code_key(m)));
+        let value = || {
+            value_key(
+                item.comment().unwrap_or_default(),
+                item.footprint().unwrap_or_default(),
+            )
+            .and_then(|k| self.by_value.get(&k))
+        };
+        code.or_else(mpn).or_else(value).copied()
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ShortageLine {
+    pub unique_id: String,
+    pub designators: Vec<String>,
+    pub comment: String,
+    /// Inventory code matched, empty if none
+    pub code: String,
+    pub location: String,
+    /// Parts for the build, spare parts included
+    pub required: u64,
+    pub available: u64,
+    pub missing: u64,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ShortageReport {
+    pub build_quantity: usize,
+    pub lines: Vec<ShortageLine>,
+}
+
+impl ShortageReport {
+    /// Lines without enough parts on hand.
+    pub fn shortages(&self) -> impl Iterator<Item = &ShortageLine> {
+        self.lines.iter().filter(|l| l.missing > 0)
+    }
+}
+
+impl Bom {
+    /// Compare the populated lines to `inventory` for `build_quantity`
+    /// boards, with the spare parts of `rules`. Call it on the merged BOM.
+    /// Lines matching the same entry share its quantity, in designator order.
+    pub fn shortage_report(
+        &self,
+        inventory: &Inventory,
+        build_quantity: usize,
+        rules: &AttritionRules,
+    ) -> ShortageReport {
+        let mut items: Vec<&Item> = self
+            .items()
+            .filter(|i| !i.is_np() && !i.designators().is_empty())
+            .collect();
+        items.sort_by(|a, b| {
+            natural_cmp(
+                a.designators().first().map_or("", |s| s.as_str()),
+                b.designators().first().map_or("", |s| s.as_str()),
+            )
+        });
+
+        let mut left: Vec<u64> = inventory.entries.iter().map(|e| e.quantity).collect();
+        let mut report = ShortageReport {
+            build_quantity,
+            lines: Vec::new(),
+        };
+        for item in items {
+            let required = rules.gross(
+                item.quantity() * build_quantity,
+                &item.category.to_string(),
+                item.footprint().unwrap_or_default(),
+            ) as u64;
+            let mut line = ShortageLine {
+                unique_id: item.unique_id().to_string(),
+                designators: item.designators().to_vec(),
+                comment: item.comment().unwrap_or_default().to_string(),
+                required,
+                ..Default::default()
+            };
+            if let Some(i) = inventory.find(item) {
+                let entry = &inventory.entries[i];
+                line.code = entry.code.clone();
+                line.location = entry.location.clone();
+                line.available = left[i];
+                left[i] = left[i].saturating_sub(required);
+            }
+            line.missing = required.saturating_sub(line.available);
+            report.lines.push(line);
+        }
+        report
+    }
+}
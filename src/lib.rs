@@ -1,5 +1,17 @@
+pub mod assembly;
+pub mod attrition;
 pub mod bom;
+pub mod columns;
+pub mod cost;
+pub mod diff;
+pub mod distributor;
+pub mod inventory;
+pub mod library;
+pub mod lookup;
 pub mod outjob;
+pub mod placement;
+pub mod project;
+pub mod style;
 pub mod utils;
 
 pub const ASCII_LOGO: &str = r#"
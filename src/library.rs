@@ -0,0 +1,167 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use super::bom::{Bom, Diagnostic, Item, Severity};
+use super::utils::comment_to_number;
+
+/// Item field of our internal article code, the "CODE internal" column.
+pub const INTERNAL_CODE: &str = "code internal";
+
+/// A part of the local library, everything but `code` is optional.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LibraryPart {
+    /// Internal article code
+    pub code: String,
+    #[serde(default)]
+    pub mpn: String,
+    #[serde(default)]
+    pub manufacturer: String,
+    /// Component value, as in the BOM comment
+    #[serde(default)]
+    pub value: String,
+    #[serde(default)]
+    pub footprint: String,
+    #[serde(default)]
+    pub description: String,
+    /// Other columns, eg. "CODE farnell" -> "9238603"
+    #[serde(default)]
+    pub fields: HashMap<String, String>,
+}
+
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct PartsLibrary {
+    parts: Vec<LibraryPart>,
+    #[serde(skip)]
+    by_code: HashMap<String, usize>,
+    #[serde(skip)]
+    by_mpn: HashMap<String, usize>,
+    #[serde(skip)]
+    by_value: HashMap<String, usize>,
+}
+
+pub(crate) fn code_key(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+/// Value and footprint as compared between BOM and library: "2k2", "2.2k"
+/// and "2200" are the same value, the F or H unit is kept so a capacitor
+/// does not match an inductor.
+pub(crate) fn value_key(value: &str, footprint: &str) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() || footprint.trim().is_empty() {
+        return None;
+    }
+    let value = match comment_to_number(value) {
+        Some(n) => {
+            let unit = match value.chars().last().map(|c| c.to_ascii_uppercase()) {
+                Some(u @ ('F' | 'H')) => u.to_string(),
+                _ => String::new(),
+            };
+            format!("{:.5e}{}", n, unit)
+        }
+        None => code_key(value),
+    };
+    Some(format!("{}|{}", value, code_key(footprint)))
+}
+
+impl PartsLibrary {
+    pub fn new(parts: Vec<LibraryPart>) -> PartsLibrary {
+        let mut lib = PartsLibrary {
+            parts,
+            ..Default::default()
+        };
+        lib.index();
+        lib
+    }
+
+    fn index(&mut self) {
+        for (i, p) in self.parts.iter().enumerate() {
+            self.by_code.entry(code_key(&p.code)).or_insert(i);
+            if !p.mpn.trim().is_empty() {
+                self.by_mpn.entry(code_key(&p.mpn)).or_insert(i);
+            }
+            if let Some(k) = value_key(&p.value, &p.footprint) {
+                self.by_value.entry(k).or_insert(i);
+            }
+        }
+    }
+
+    /// Load a json library: `{ "parts": [ { "code": .., "mpn": .., .. } ] }`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PartsLibrary> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+        let mut lib: PartsLibrary =
+            serde_json::from_str(&data).with_context(|| format!("{}", path.display()))?;
+        lib.index();
+        Ok(lib)
+    }
+
+    pub fn parts(&self) -> &[LibraryPart] {
+        &self.parts
+    }
+
+    /// Library part of `item`, by internal code, then MPN, then value and
+    /// footprint.
+    pub fn find(&self, item: &Item) -> Option<&LibraryPart> {
+        let code = item
+            .field(INTERNAL_CODE)
+            .map(|f| f.to_string())
+            .and_then(|c| self.by_code.get(&code_key(&c)));
+        let mpn = || item.mpn().and_then(|m| self.by_mpn.get(&code_key(m)));
+        let value = || {
+            value_key(
+                item.comment().unwrap_or_default(),
+                item.footprint().unwrap_or_default(),
+            )
+            .and_then(|k| self.by_value.get(&k))
+        };
+        code.or_else(mpn).or_else(value).map(|i| &self.parts[*i])
+    }
+
+    /// Warning for each line of `bom` with no library part.
+    pub fn unmatched(&self, bom: &Bom) -> Vec<Diagnostic> {
+        let mut diags: Vec<Diagnostic> = bom
+            .items()
+            .filter(|i| !i.designators().is_empty() && self.find(i).is_none())
+            .map(|i| Diagnostic {
+                unique_id: i.unique_id().to_string(),
+                severity: Severity::Warning,
+                message: format!("{}: no library match", i.designators().join(", ")),
+            })
+            .collect();
+        diags.sort_by(|a, b| a.unique_id.cmp(&b.unique_id));
+        diags
+    }
+}
+
+impl Bom {
+    /// Fill the blank fields of the items from `library` and attach the
+    /// internal code. Call it before merging: a line changes its unique id
+    /// when a merge key gets filled.
+    pub fn enrich(&mut self, library: &PartsLibrary) {
+        let merge_keys = self.merge_keys().to_vec();
+        for item in self.items_mut() {
+            let part = match library.find(item) {
+                Some(p) => p.clone(),
+                None => continue,
+            };
+            item.fill_field(INTERNAL_CODE, &part.code);
+            item.fill_field("comment", &part.value);
+            item.fill_field("footprint", &part.footprint);
+            item.fill_field("description", &part.description);
+            item.fill_field("manufacturer", &part.manufacturer);
+            item.fill_field("mpn", &part.mpn);
+            for (k, v) in part.fields.iter() {
+                item.fill_field(k, v);
+            }
+            item.refresh_unique_id(&merge_keys);
+        }
+    }
+}
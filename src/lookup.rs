@@ -0,0 +1,364 @@
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use strum_macros::Display as EnumDisplay;
+use ureq::{Agent, AgentBuilder};
+use url::Url;
+
+use super::bom::{Diagnostic, ItemsTable, Severity};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, EnumDisplay)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Lifecycle {
+    Active,
+    /// Not recommended for new designs
+    Nrnd,
+    Obsolete,
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+/// What a distributor knows about a part.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PartInfo {
+    pub mpn: String,
+    #[serde(default)]
+    pub manufacturer: String,
+    #[serde(default)]
+    pub stock: Option<u64>,
+    /// Unit price for one piece
+    #[serde(default)]
+    pub price: Option<f64>,
+    #[serde(default)]
+    pub currency: String,
+    #[serde(default)]
+    pub lifecycle: Lifecycle,
+}
+
+/// Source of part data, Ok(None) when the part is unknown to it.
+pub trait LookupBackend {
+    fn fetch(&self, mpn: &str) -> Result<Option<PartInfo>>;
+}
+
+/// Parts kept in memory, eg. for tests or a pre-loaded catalogue.
+#[derive(Default, Debug, Clone)]
+pub struct MemoryBackend {
+    parts: HashMap<String, PartInfo>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, part: PartInfo) {
+        self.parts.insert(part.mpn.to_uppercase(), part);
+    }
+}
+
+impl LookupBackend for MemoryBackend {
+    fn fetch(&self, mpn: &str) -> Result<Option<PartInfo>> {
+        Ok(self.parts.get(&mpn.to_uppercase()).cloned())
+    }
+}
+
+/// Distributor style json API over http or https: `GET <url>/parts/<mpn>`
+/// answers a `PartInfo` or 404. The API key goes in a `X-Api-Key` header,
+/// to the host of `url` only.
+#[derive(Debug, Clone)]
+pub struct HttpBackend {
+    url: Url,
+    api_key: Option<String>,
+    agent: Agent,
+}
+
+/// Redirects followed before giving up.
+const MAX_REDIRECTS: usize = 5;
+
+/// Biggest answer read, a part is a few hundred bytes.
+const MAX_BODY: u64 = 1 << 20;
+
+fn url_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn agent(timeout: Duration) -> Agent {
+    // Redirects are followed by `get`, which knows where the key may go
+    AgentBuilder::new().timeout(timeout).redirects(0).build()
+}
+
+impl HttpBackend {
+    /// `url` is like "https://api.example.com/v1".
+    pub fn new(url: &str) -> Result<HttpBackend> {
+        let url = Url::parse(url.trim_end_matches('/'))
+            .with_context(|| format!("Invalid lookup url: {}", url))?;
+        if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+            bail!("Lookup urls are http:// or https://, not {}", url);
+        }
+        Ok(HttpBackend {
+            url,
+            api_key: None,
+            agent: agent(Duration::from_secs(10)),
+        })
+    }
+
+    pub fn with_api_key(mut self, key: &str) -> Self {
+        self.api_key = Some(key.to_string());
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.agent = agent(timeout);
+        self
+    }
+
+    /// Status and body of a GET, following the redirects. The key is only
+    /// sent to the scheme, host and port of the backend url.
+    fn get(&self, url: Url) -> Result<(u16, Vec<u8>)> {
+        let mut url = url;
+        for _ in 0..=MAX_REDIRECTS {
+            let mut request = self.agent.request_url("GET", &url);
+            request = request.set("Accept", "application/json");
+            if let Some(key) = &self.api_key {
+                if url.origin() == self.url.origin() {
+                    request = request.set("X-Api-Key", key);
+                }
+            }
+            let response = match request.call() {
+                Ok(r) => r,
+                Err(ureq::Error::Status(_, r)) => r,
+                Err(e) => return Err(e).with_context(|| format!("Unable to get {}", url)),
+            };
+            if !matches!(response.status(), 301 | 302 | 303 | 307 | 308) {
+                let status = response.status();
+                let mut body = Vec::new();
+                response
+                    .into_reader()
+                    .take(MAX_BODY + 1)
+                    .read_to_end(&mut body)
+                    .with_context(|| format!("Unable to read {}", url))?;
+                if body.len() as u64 > MAX_BODY {
+                    bail!("{}: answer over {} bytes", url, MAX_BODY);
+                }
+                return Ok((status, body));
+            }
+            let location = response
+                .header("location")
+                .ok_or_else(|| anyhow!("{}: redirect without location", url))?;
+            let next = url
+                .join(location)
+                .with_context(|| format!("{}: invalid redirect to {}", url, location))?;
+            debug!("{} redirected to {}", url, next);
+            url = next;
+        }
+        bail!("{}: more than {} redirects", url, MAX_REDIRECTS)
+    }
+}
+
+impl LookupBackend for HttpBackend {
+    fn fetch(&self, mpn: &str) -> Result<Option<PartInfo>> {
+        let url = format!(
+            "{}/parts/{}",
+            self.url.as_str().trim_end_matches('/'),
+            url_encode(mpn)
+        );
+        let url = Url::parse(&url)?;
+        match self.get(url.clone())? {
+            (200, body) => Ok(Some(serde_json::from_slice(&body)?)),
+            (404, _) => Ok(None),
+            (status, _) => bail!("{}: http status {}", url, status),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct CacheEntry {
+    mpn: String,
+    /// Seconds from the epoch
+    fetched: u64,
+    part: Option<PartInfo>,
+}
+
+/// Backend answers saved as json files, one per part, valid for `max_age`.
+/// Unknown parts are cached too, so they are not asked again and again.
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+    max_age: Duration,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl DiskCache {
+    pub fn new<P: Into<PathBuf>>(dir: P, max_age: Duration) -> DiskCache {
+        DiskCache {
+            dir: dir.into(),
+            max_age,
+        }
+    }
+
+    fn path(&self, mpn: &str) -> PathBuf {
+        let name: String = mpn
+            .to_uppercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        self.dir.join(format!("{}.json", name))
+    }
+
+    /// Some(answer) when the part is in the cache and not expired.
+    fn get(&self, mpn: &str) -> Option<Option<PartInfo>> {
+        let data = fs::read_to_string(self.path(mpn)).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&data).ok()?;
+        // Different parts may share a file name
+        if !entry.mpn.eq_ignore_ascii_case(mpn)
+            || now().saturating_sub(entry.fetched) > self.max_age.as_secs()
+        {
+            return None;
+        }
+        Some(entry.part)
+    }
+
+    fn put(&self, mpn: &str, part: &Option<PartInfo>) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let entry = CacheEntry {
+            mpn: mpn.to_string(),
+            fetched: now(),
+            part: part.clone(),
+        };
+        fs::write(self.path(mpn), serde_json::to_string(&entry)?)?;
+        Ok(())
+    }
+}
+
+pub struct PartLookup<B: LookupBackend> {
+    backend: B,
+    cache: Option<DiskCache>,
+}
+
+impl<B: LookupBackend> PartLookup<B> {
+    pub fn new(backend: B) -> Self {
+        PartLookup {
+            backend,
+            cache: None,
+        }
+    }
+
+    pub fn with_cache(mut self, cache: DiskCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Part data from the cache, or from the backend when missing or expired.
+    pub fn lookup(&self, mpn: &str) -> Result<Option<PartInfo>> {
+        if let Some(part) = self.cache.as_ref().and_then(|c| c.get(mpn)) {
+            debug!("Lookup {}: from cache", mpn);
+            return Ok(part);
+        }
+        let part = self.backend.fetch(mpn)?;
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.put(mpn, &part) {
+                warn!("Lookup {}: unable to cache: {}", mpn, e);
+            }
+        }
+        Ok(part)
+    }
+
+    /// Look up the MPN of every line and append "Stock", "Lifecycle" and
+    /// "Distributor price" columns. Unknown, obsolete, NRND and short stock
+    /// parts for `build_quantity` boards go to the table diagnostics.
+    pub fn annotate(&self, table: &mut ItemsTable, build_quantity: usize) {
+        let mpn_col = table.headers.iter().position(|h| h == "MPN");
+
+        let quantities = table.purchase_quantities(build_quantity);
+        for (row, needed) in table.rows.iter_mut().zip(quantities) {
+            let mpn = mpn_col
+                .and_then(|i| row.fields.get(i))
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty() && s != "-");
+            let mut diag = |severity, message: String| {
+                table.diagnostics.push(Diagnostic {
+                    unique_id: row.unique_id.clone(),
+                    severity,
+                    message,
+                })
+            };
+
+            let part = match mpn.as_deref().map(|m| (m, self.lookup(m))) {
+                Some((_, Ok(Some(part)))) => part,
+                Some((mpn, Ok(None))) => {
+                    diag(Severity::Warning, format!("{}: not found", mpn));
+                    row.fields
+                        .extend(["".to_string(), "".to_string(), "".to_string()]);
+                    continue;
+                }
+                Some((mpn, Err(e))) => {
+                    diag(Severity::Error, format!("{}: lookup failed: {:#}", mpn, e));
+                    row.fields
+                        .extend(["".to_string(), "".to_string(), "".to_string()]);
+                    continue;
+                }
+                None => {
+                    row.fields
+                        .extend(["".to_string(), "".to_string(), "".to_string()]);
+                    continue;
+                }
+            };
+
+            let needed = needed as u64;
+            match part.stock {
+                Some(0) => diag(Severity::Error, format!("{}: out of stock", part.mpn)),
+                Some(stock) if stock < needed => diag(
+                    Severity::Warning,
+                    format!("{}: {} in stock, {} needed", part.mpn, stock, needed),
+                ),
+                _ => {}
+            }
+            match part.lifecycle {
+                Lifecycle::Obsolete => diag(Severity::Error, format!("{}: obsolete", part.mpn)),
+                Lifecycle::Nrnd => diag(
+                    Severity::Warning,
+                    format!("{}: not recommended for new designs", part.mpn),
+                ),
+                _ => {}
+            }
+
+            row.fields
+                .push(part.stock.map_or(String::new(), |s| s.to_string()));
+            row.fields.push(part.lifecycle.to_string());
+            row.fields.push(
+                part.price
+                    .map_or(String::new(), |p| format!("{:.4} {}", p, part.currency))
+                    .trim()
+                    .to_string(),
+            );
+        }
+        for row in table.dnp.iter_mut() {
+            row.fields
+                .extend(["".to_string(), "".to_string(), "".to_string()]);
+        }
+        table.headers.extend(
+            ["Stock", "Lifecycle", "Distributor price"]
+                .iter()
+                .map(|s| s.to_string()),
+        );
+    }
+}
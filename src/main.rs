@@ -2,7 +2,7 @@ use askama::Template;
 use axum::{
     body::Bytes,
     extract::Multipart,
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{Html, IntoResponse, Response},
     routing::{get, post},
     BoxError, Json, Router,
@@ -10,14 +10,29 @@ use axum::{
 use futures::{Stream, TryStreamExt};
 use glob::glob;
 use serde::{Deserialize, Serialize};
-use std::{io, net::SocketAddr, path::Path, vec};
+use std::{
+    io::{self, Cursor},
+    net::SocketAddr,
+    path::Path,
+    vec,
+};
 use tokio::{fs::File, io::BufWriter};
 use tokio_util::io::StreamReader;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use mergebom_web::{
-    bom::{merge_key_list, Bom, ItemsTable},
-    outjob::OutJobXlsx,
+    assembly::AssemblySplit,
+    attrition::{AttritionRule, AttritionRules},
+    bom::{merge_key_list, Bom, BomFormat, Diagnostic, ItemsTable, SortBy, Variant},
+    columns::{Column, ColumnSpec},
+    diff::BomDiff,
+    distributor::Distributor,
+    inventory::Inventory,
+    library::PartsLibrary,
+    outjob::{OutFormat, XlsxOptions, XlsxSheets},
+    placement::Placements,
+    project::MergeProject,
+    style::XlsxStyle,
 };
 
 const STATIC_DIRECTORY: &str = "static";
@@ -36,8 +51,16 @@ async fn main() {
     let app: _ = Router::new()
         .route("/", get(render_index))
         .route("/view", post(merge_view_post))
+        .route("/download", post(merge_download_post))
+        .route("/diff", post(diff_post))
+        .route("/project", post(project_post))
+        .route("/cart", post(cart_post))
+        .route("/stock", post(stock_post))
+        .route("/placement", post(placement_post))
+        .route("/assembly", post(assembly_post))
         .route("/jobs", post(jobs_done))
         .route("/upload", post(accept_form))
+        .route("/view_upload", post(merge_upload_post))
         .merge(axum_extra::routing::SpaRouter::new(
             "/static",
             STATIC_DIRECTORY,
@@ -120,29 +143,384 @@ async fn jobs_done() -> Json<JobDone> {
     })
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 struct MergeCfg {
     merge_file_name: String,
     merge_files: Vec<String>,
     merge_keys: Vec<String>,
+    #[serde(default)]
+    variants: Vec<Variant>,
+    #[serde(default)]
+    source_columns: bool,
+    #[serde(default)]
+    sort_by: SortBy,
+    /// Uploaded price list to cost the merge with
+    #[serde(default)]
+    price_list: Option<String>,
+    #[serde(default)]
+    build_quantity: usize,
+    /// Uploaded parts library to fill the blank fields with
+    #[serde(default)]
+    library: Option<String>,
+    /// Spare parts added to the purchase quantities
+    #[serde(default)]
+    attrition: Vec<AttritionRule>,
+    /// Format of the merged file, xlsx by default
+    #[serde(default)]
+    format: OutFormat,
+    /// Add cover, diagnostics and per source sheets to the xlsx
+    #[serde(default)]
+    assembly: bool,
+    /// Uploaded style of the xlsx tables
+    #[serde(default)]
+    style: Option<String>,
+    #[serde(default)]
+    revision: String,
+    /// Columns of the merged file, all of them if empty
+    #[serde(default)]
+    columns: Vec<Column>,
+}
+
+impl MergeCfg {
+    /// Project of the merge, the files are in the uploads directory.
+    fn project(&self) -> MergeProject {
+        let uploaded = |f: &String| Path::new(UPLOADS_DIRECTORY).join(f);
+        let files: Vec<_> = self.merge_files.iter().map(uploaded).collect();
+
+        let mut project = MergeProject::new(files.as_slice(), &self.merge_keys);
+        project.options.variants = self.variants.clone();
+        project.options.source_columns = self.source_columns;
+        project.options.sort_by = self.sort_by;
+        project.options.price_list = self
+            .price_list
+            .as_ref()
+            .filter(|f| path_is_valid(f))
+            .map(uploaded);
+        project.options.build_quantity = self.build_quantity;
+        project.options.revision = self.revision.clone();
+        project.options.attrition = AttritionRules::new(self.attrition.clone());
+        project.options.columns = ColumnSpec::new(self.columns.clone());
+        project.options.library = self
+            .library
+            .as_ref()
+            .filter(|f| path_is_valid(f))
+            .map(uploaded);
+        project
+    }
+
+    /// The xlsx sheets and style asked for.
+    fn xlsx_options(&self) -> anyhow::Result<XlsxOptions> {
+        let mut options = XlsxOptions::default();
+        if self.assembly {
+            options.sheets = XlsxSheets::all();
+        }
+        if let Some(style) = self.style.as_ref().filter(|f| path_is_valid(f)) {
+            options.style = XlsxStyle::load(Path::new(UPLOADS_DIRECTORY).join(style))?;
+        }
+        Ok(options)
+    }
+
+    /// Name of the merged file, with the extension of its format.
+    fn output_name(&self) -> String {
+        let name = match self.merge_file_name.as_str() {
+            name if path_is_valid(name) => name,
+            _ => "merged_bom",
+        };
+        Path::new(name)
+            .with_extension(self.format.extension())
+            .to_string_lossy()
+            .to_string()
+    }
 }
 
 async fn merge_view_post(Json(payload): Json<MergeCfg>) -> Json<ItemsTable> {
+    let file_name = payload.output_name();
+    let mut project = payload.project();
+    let mut data = match project.run() {
+        Ok(data) => data,
+        Err(e) => {
+            tracing::error!("{}", e);
+            ItemsTable::default()
+        }
+    };
+    data.apply_columns(&project.options.columns);
+
+    // Keep the project beside the merged file, to inspect or run it again
+    let output = Path::new(MERGED_DIRECTORY).join(&file_name);
+    if let Err(e) = project.save(output.with_extension("project.json")) {
+        tracing::error!("{:#}", e);
+    }
+    let meta = project.out_job_meta(&file_name);
+    let written = payload
+        .xlsx_options()
+        .and_then(|xlsx| payload.format.out_job(&output, &xlsx))
+        .and_then(|mut job| job.write(&data, &meta));
+    if let Err(e) = written {
+        tracing::error!("{:#}", e);
+    }
+    Json(data)
+}
+
+// Handler that merges like "/view" but sends the merged file back in the
+// response, in the requested format, and keeps nothing on the server.
+async fn merge_download_post(Json(payload): Json<MergeCfg>) -> Response {
+    let file_name = payload.output_name();
+    let mut project = payload.project();
+    let bytes = project.run().and_then(|mut data| {
+        data.apply_columns(&project.options.columns);
+        let meta = project.out_job_meta(&file_name);
+        payload
+            .format
+            .to_bytes(&data, &meta, &payload.xlsx_options()?)
+    });
+    match bytes {
+        Ok(bytes) => (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    payload.format.content_type().to_string(),
+                ),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", file_name),
+                ),
+            ],
+            bytes,
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("{:#}", e);
+            (StatusCode::UNPROCESSABLE_ENTITY, format!("{:#}", e)).into_response()
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+struct ProjectCfg {
+    project_file: String,
+    #[serde(default)]
+    run: bool,
+}
+
+// Handler that loads a project saved in the merged directory and returns its
+// table, merging the inputs again when `run` is set.
+async fn project_post(Json(payload): Json<ProjectCfg>) -> Response {
+    if !path_is_valid(&payload.project_file) {
+        return (StatusCode::BAD_REQUEST, "Invalid path".to_owned()).into_response();
+    }
+    let path = Path::new(MERGED_DIRECTORY).join(&payload.project_file);
+    let data = MergeProject::load(&path).and_then(|mut project| {
+        let mut data = if payload.run {
+            let data = project.run()?;
+            project.save(&path)?;
+            data
+        } else {
+            project.table()?
+        };
+        data.apply_columns(&project.options.columns);
+        Ok(data)
+    });
+    match data {
+        Ok(data) => Json(data).into_response(),
+        Err(e) => {
+            tracing::error!("{:#}", e);
+            (StatusCode::UNPROCESSABLE_ENTITY, format!("{:#}", e)).into_response()
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+struct CartCfg {
+    merge_files: Vec<String>,
+    merge_keys: Vec<String>,
+    distributor: Distributor,
+}
+
+// Handler that returns the cart upload csv of a distributor for the merged
+// uploaded files, order code problems are only logged.
+async fn cart_post(Json(payload): Json<CartCfg>) -> Response {
     let files: Vec<_> = payload
         .merge_files
         .iter()
         .map(|f| Path::new(UPLOADS_DIRECTORY).join(f))
         .collect();
 
-    let mut file_name = "merged_bom.xlsx".to_string();
-    if !payload.merge_file_name.is_empty() {
-        file_name = payload.merge_file_name;
+    let bom = Bom::loader(files.as_slice(), &payload.merge_keys).merge();
+    for issue in bom.order_code_issues() {
+        tracing::warn!("{}", issue);
     }
+    let mut csv = Vec::new();
+    match bom.write_cart(payload.distributor, &mut csv) {
+        Ok(_) => ([(header::CONTENT_TYPE, "text/csv")], csv).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+struct StockCfg {
+    merge_files: Vec<String>,
+    merge_keys: Vec<String>,
+    /// Uploaded warehouse inventory
+    inventory: String,
+    #[serde(default)]
+    build_quantity: usize,
+    /// Uploaded parts library to find the internal codes
+    #[serde(default)]
+    library: Option<String>,
+    /// Spare parts added to the required quantities
+    #[serde(default)]
+    attrition: Vec<AttritionRule>,
+}
 
+// Handler that returns the shortage report of the merged uploaded files
+// against an uploaded inventory.
+async fn stock_post(Json(payload): Json<StockCfg>) -> Response {
+    if !path_is_valid(&payload.inventory) {
+        return (StatusCode::BAD_REQUEST, "Invalid path".to_owned()).into_response();
+    }
+    let files: Vec<_> = payload
+        .merge_files
+        .iter()
+        .map(|f| Path::new(UPLOADS_DIRECTORY).join(f))
+        .collect();
+
+    let inventory = match Inventory::load(Path::new(UPLOADS_DIRECTORY).join(&payload.inventory)) {
+        Ok(inventory) => inventory,
+        Err(e) => {
+            tracing::error!("{:#}", e);
+            return (StatusCode::UNPROCESSABLE_ENTITY, format!("{:#}", e)).into_response();
+        }
+    };
+    let mut bom = Bom::loader(files.as_slice(), &payload.merge_keys);
+    if let Some(library) = payload.library.filter(|f| path_is_valid(f)) {
+        match PartsLibrary::load(Path::new(UPLOADS_DIRECTORY).join(library)) {
+            Ok(library) => bom.enrich(&library),
+            Err(e) => tracing::error!("{:#}", e),
+        }
+    }
+    Json(bom.merge().shortage_report(
+        &inventory,
+        payload.build_quantity.max(1),
+        &AttritionRules::new(payload.attrition),
+    ))
+    .into_response()
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+struct PlacementCfg {
+    merge_files: Vec<String>,
+    merge_keys: Vec<String>,
+    /// Uploaded pick-and-place files
+    placement_files: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct PlacementResult {
+    diagnostics: Vec<Diagnostic>,
+    centroid: Placements,
+}
+
+// Handler that checks uploaded pick-and-place files against the merged
+// uploaded BOM files and returns the problems and the filled centroid.
+async fn placement_post(Json(payload): Json<PlacementCfg>) -> Response {
+    if !payload.placement_files.iter().all(|f| path_is_valid(f)) {
+        return (StatusCode::BAD_REQUEST, "Invalid path".to_owned()).into_response();
+    }
+    let uploaded = |f: &String| Path::new(UPLOADS_DIRECTORY).join(f);
+    let mut placements = Placements::new();
+    for path in payload.placement_files.iter().map(uploaded) {
+        match Placements::load(&path) {
+            Ok(loaded) => {
+                for p in loaded.placements() {
+                    placements.add(p.clone());
+                }
+            }
+            Err(e) => {
+                tracing::error!("{:#}", e);
+                return (StatusCode::UNPROCESSABLE_ENTITY, format!("{:#}", e)).into_response();
+            }
+        }
+    }
+    let files: Vec<_> = payload.merge_files.iter().map(uploaded).collect();
+    let bom = Bom::loader(files.as_slice(), &payload.merge_keys).merge();
+    Json(PlacementResult {
+        diagnostics: bom.check_placements(&placements),
+        centroid: bom.centroid(&placements),
+    })
+    .into_response()
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+struct AssemblyCfg {
+    merge_files: Vec<String>,
+    merge_keys: Vec<String>,
+    #[serde(default)]
+    sort_by: SortBy,
+}
+
+// Handler that splits the uploaded files by assembly step, each step merged
+// on its own.
+async fn assembly_post(Json(payload): Json<AssemblyCfg>) -> Json<Vec<AssemblySplit>> {
+    let files: Vec<_> = payload
+        .merge_files
+        .iter()
+        .map(|f| Path::new(UPLOADS_DIRECTORY).join(f))
+        .collect();
     let bom = Bom::loader(files.as_slice(), &payload.merge_keys);
-    let data = bom.merge().odered_vector_table();
-    OutJobXlsx::new(Path::new(MERGED_DIRECTORY).join(file_name)).write(&data);
-    Json(data)
+    Json(bom.assembly_splits(payload.sort_by))
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+struct DiffCfg {
+    old_files: Vec<String>,
+    new_files: Vec<String>,
+    merge_keys: Vec<String>,
+}
+
+async fn diff_post(Json(payload): Json<DiffCfg>) -> Json<BomDiff> {
+    let uploaded = |files: &[String]| -> Vec<_> {
+        files
+            .iter()
+            .map(|f| Path::new(UPLOADS_DIRECTORY).join(f))
+            .collect()
+    };
+
+    let old = Bom::loader(uploaded(&payload.old_files).as_slice(), &payload.merge_keys);
+    let new = Bom::loader(uploaded(&payload.new_files).as_slice(), &payload.merge_keys);
+    Json(old.diff(&new))
+}
+
+// Handler that merges the files of a multipart form in memory, without saving
+// them in the uploads directory. Text fields named "merge_keys" are the keys.
+async fn merge_upload_post(mut multipart: Multipart) -> Json<ItemsTable> {
+    let mut merge_keys = vec![];
+    let mut files = vec![];
+    while let Ok(Some(field)) = multipart.next_field().await {
+        let name = field.name().unwrap_or_default().to_owned();
+        if let Some(file_name) = field.file_name().map(|f| f.to_owned()) {
+            match field.bytes().await {
+                Ok(data) => files.push((file_name, data)),
+                Err(e) => tracing::error!("failed to read {}: {:?}", file_name, e),
+            }
+        } else if name == "merge_keys" {
+            if let Ok(key) = field.text().await {
+                merge_keys.push(key);
+            }
+        }
+    }
+
+    let mut bom = Bom::new(&merge_keys);
+    for (file_name, data) in files {
+        match BomFormat::from_path(&file_name) {
+            Some(format) => {
+                if let Err(e) = bom.read(Cursor::new(data), format, &file_name) {
+                    tracing::error!("{}: {}", file_name, e);
+                }
+            }
+            None => tracing::warn!("{}: unknown BOM format, skip", file_name),
+        }
+    }
+    Json(bom.merge().odered_vector_table())
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
@@ -1,84 +1,995 @@
-use std::path::Path;
+use anyhow::{bail, Context, Result};
+use askama::Template;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use strum_macros::{Display as EnumDisplay, EnumString};
+use tempfile::TempDir;
 
-use super::bom::ItemsTable;
-use xlsxwriter::prelude::{FormatAlignment, FormatBorder, FormatColor};
-use xlsxwriter::{Format, Workbook};
+use super::assembly::AssemblySplit;
+use super::bom::{ItemView, ItemsTable, Source, MERGED_NAME, MERGED_QUANTITY_NAME, MERGED_SHEET};
+use super::diff::BomDiff;
+use super::inventory::ShortageReport;
+use super::style::{TableFormats, XlsxStyle};
+use super::utils::column_name;
+use xlsxwriter::prelude::{FormatColor, RowColOptions, LXW_DEF_ROW_HEIGHT};
+use xlsxwriter::{Format, Workbook, Worksheet};
+
+/// An input of the merge and the CRC32 of its content, empty if unreadable.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct InputFile {
+    pub name: String,
+    pub crc32: String,
+}
+
+/// What a table was made from, written beside it.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct OutJobMeta {
+    pub title: String,
+    #[serde(default)]
+    pub revision: String,
+    /// Day of the merge, "YYYY-MM-DD"
+    #[serde(default)]
+    pub date: String,
+    pub inputs: Vec<InputFile>,
+    pub merge_keys: Vec<String>,
+}
+
+impl OutJobMeta {
+    /// Input file names, comma separated.
+    pub fn input_names(&self) -> String {
+        let names: Vec<&str> = self.inputs.iter().map(|i| i.name.as_str()).collect();
+        names.join(", ")
+    }
+}
+
+/// A writer of merged tables, eg. xlsx for the assembler or csv for the ERP.
+pub trait OutJob {
+    fn write(&mut self, data: &ItemsTable, meta: &OutJobMeta) -> Result<()>;
+}
+
+#[derive(
+    Default, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, EnumString, EnumDisplay,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum OutFormat {
+    #[default]
+    Xlsx,
+    Csv,
+    Json,
+    Markdown,
+    Html,
+    /// Html to print, or to save as pdf from a browser
+    Report,
+}
+
+impl OutFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Xlsx => "xlsx",
+            Self::Csv => "csv",
+            Self::Json => "json",
+            Self::Markdown => "md",
+            Self::Html | Self::Report => "html",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            Self::Csv => "text/csv",
+            Self::Json => "application/json",
+            Self::Markdown => "text/markdown",
+            Self::Html | Self::Report => "text/html",
+        }
+    }
+
+    /// Writer of this format to exactly `path`, `xlsx` is only used by the
+    /// xlsx one.
+    pub fn out_job<P: AsRef<Path>>(&self, path: P, xlsx: &XlsxOptions) -> Result<Box<dyn OutJob>> {
+        let path = path.as_ref();
+        if *self == Self::Xlsx {
+            return Ok(Box::new(OutJobXlsx::new(path)?.with_options(xlsx)));
+        }
+        let file =
+            File::create(path).with_context(|| format!("Unable to create {}", path.display()))?;
+        Ok(self.text_job(BufWriter::new(file)))
+    }
+
+    fn text_job<'a, W: Write + 'a>(&self, wr: W) -> Box<dyn OutJob + 'a> {
+        match self {
+            Self::Csv => Box::new(OutJobCsv::new(wr)),
+            Self::Json => Box::new(OutJobJson::new(wr)),
+            Self::Markdown => Box::new(OutJobMarkdown::new(wr)),
+            Self::Report => Box::new(OutJobReport::new(wr)),
+            _ => Box::new(OutJobHtml::new(wr)),
+        }
+    }
+
+    /// The file of this format in memory, eg. to send it back over http.
+    pub fn to_bytes(
+        &self,
+        data: &ItemsTable,
+        meta: &OutJobMeta,
+        xlsx: &XlsxOptions,
+    ) -> Result<Vec<u8>> {
+        if *self == Self::Xlsx {
+            let mut job = OutJobXlsx::in_memory()?.with_options(xlsx);
+            job.write(data, meta)?;
+            return job.into_bytes();
+        }
+        let mut bytes = Vec::new();
+        self.text_job(&mut bytes).write(data, meta)?;
+        Ok(bytes)
+    }
+}
+
+/// Rows of the same category, the categories in order of first appearance,
+/// so the rows need not be sorted.
+fn category_groups(rows: &[ItemView]) -> Vec<(&str, Vec<&ItemView>)> {
+    let mut groups: Vec<(&str, Vec<&ItemView>)> = Vec::new();
+    for row in rows.iter() {
+        match groups.iter_mut().find(|(c, _)| *c == row.category) {
+            Some((_, g)) => g.push(row),
+            None => groups.push((&row.category, vec![row])),
+        }
+    }
+    groups
+}
+
+/// Flat csv for imports: the headers and the populated rows, no category
+/// lines.
+pub struct OutJobCsv<W: Write> {
+    wr: W,
+}
+
+impl<W: Write> OutJobCsv<W> {
+    pub fn new(wr: W) -> Self {
+        OutJobCsv { wr }
+    }
+}
+
+impl<W: Write> OutJob for OutJobCsv<W> {
+    fn write(&mut self, data: &ItemsTable, _meta: &OutJobMeta) -> Result<()> {
+        let mut wr = csv::Writer::from_writer(&mut self.wr);
+        wr.write_record(&data.headers)?;
+        for row in data.rows.iter() {
+            wr.write_record(&row.fields)?;
+        }
+        wr.flush()?;
+        Ok(())
+    }
+}
+
+/// The whole table as json, `{ "meta": .., "table": .. }`.
+pub struct OutJobJson<W: Write> {
+    wr: W,
+}
+
+impl<W: Write> OutJobJson<W> {
+    pub fn new(wr: W) -> Self {
+        OutJobJson { wr }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonJob<'a> {
+    meta: &'a OutJobMeta,
+    table: &'a ItemsTable,
+}
+
+impl<W: Write> OutJob for OutJobJson<W> {
+    fn write(&mut self, data: &ItemsTable, meta: &OutJobMeta) -> Result<()> {
+        serde_json::to_writer_pretty(&mut self.wr, &JsonJob { meta, table: data })?;
+        self.wr.flush()?;
+        Ok(())
+    }
+}
+
+/// Markdown for the wiki, one table per category.
+pub struct OutJobMarkdown<W: Write> {
+    wr: W,
+}
+
+impl<W: Write> OutJobMarkdown<W> {
+    pub fn new(wr: W) -> Self {
+        OutJobMarkdown { wr }
+    }
+
+    fn table(&mut self, headers: &[String], rows: &[&ItemView]) -> Result<()> {
+        let cell = |s: &str| s.replace('|', "\\|").replace('\n', "<br>");
+        let line = |cells: &[String]| {
+            let cells: Vec<String> = cells.iter().map(|c| cell(c)).collect();
+            format!("| {} |", cells.join(" | "))
+        };
+        writeln!(self.wr, "{}", line(headers))?;
+        writeln!(self.wr, "|{}", "---|".repeat(headers.len()))?;
+        for row in rows.iter() {
+            writeln!(self.wr, "{}", line(&row.fields))?;
+        }
+        writeln!(self.wr)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> OutJob for OutJobMarkdown<W> {
+    fn write(&mut self, data: &ItemsTable, meta: &OutJobMeta) -> Result<()> {
+        writeln!(self.wr, "# {}\n", meta.title)?;
+        if !meta.revision.is_empty() {
+            writeln!(self.wr, "Revision: {}  ", meta.revision)?;
+        }
+        if !meta.date.is_empty() {
+            writeln!(self.wr, "Date: {}  ", meta.date)?;
+        }
+        if !meta.inputs.is_empty() {
+            writeln!(self.wr, "Inputs: {}  ", meta.input_names())?;
+        }
+        if !meta.merge_keys.is_empty() {
+            writeln!(self.wr, "Merge keys: {}  ", meta.merge_keys.join(", "))?;
+        }
+        writeln!(self.wr)?;
+
+        for (category, rows) in category_groups(&data.rows) {
+            writeln!(
+                self.wr,
+                "## {}\n",
+                category.trim_matches(|c| c == '*' || c == ' ')
+            )?;
+            self.table(&data.headers, &rows)?;
+        }
+        if !data.dnp.is_empty() {
+            writeln!(self.wr, "## Not populated\n")?;
+            let rows: Vec<&ItemView> = data.dnp.iter().collect();
+            self.table(&data.headers, &rows)?;
+        }
+        if let Some(cost) = &data.cost {
+            writeln!(
+                self.wr,
+                "**Total {}: {:.2}** for {} boards\n",
+                cost.currency, cost.total, cost.build_quantity
+            )?;
+        }
+        if !data.diagnostics.is_empty() {
+            writeln!(self.wr, "## Diagnostics\n")?;
+            for d in data.diagnostics.iter() {
+                writeln!(self.wr, "- {}", d)?;
+            }
+        }
+        self.wr.flush()?;
+        Ok(())
+    }
+}
+
+struct HtmlGroup<'a> {
+    category: &'a str,
+    rows: Vec<&'a ItemView>,
+}
+
+#[derive(Template)]
+#[template(path = "outjob.html")]
+struct HtmlJob<'a> {
+    meta: &'a OutJobMeta,
+    data: &'a ItemsTable,
+    groups: Vec<HtmlGroup<'a>>,
+}
+
+/// Plain html page, one table with a line per category.
+pub struct OutJobHtml<W: Write> {
+    wr: W,
+}
+
+impl<W: Write> OutJobHtml<W> {
+    pub fn new(wr: W) -> Self {
+        OutJobHtml { wr }
+    }
+}
+
+impl<W: Write> OutJob for OutJobHtml<W> {
+    fn write(&mut self, data: &ItemsTable, meta: &OutJobMeta) -> Result<()> {
+        let groups = category_groups(&data.rows)
+            .into_iter()
+            .map(|(category, rows)| HtmlGroup { category, rows })
+            .collect();
+        let html = HtmlJob { meta, data, groups }.render()?;
+        self.wr.write_all(html.as_bytes())?;
+        self.wr.flush()?;
+        Ok(())
+    }
+}
+
+struct ReportSection<'a> {
+    category: &'a str,
+    rows: Vec<&'a ItemView>,
+    parts: usize,
+}
+
+#[derive(Template)]
+#[template(path = "report.html")]
+struct ReportJob<'a> {
+    meta: &'a OutJobMeta,
+    data: &'a ItemsTable,
+    sections: Vec<ReportSection<'a>>,
+    parts: usize,
+    quantity: Option<usize>,
+}
+
+impl ReportJob<'_> {
+    fn is_quantity(&self, column: &usize) -> bool {
+        self.quantity == Some(*column)
+    }
+}
+
+/// Self-contained html report for the design reviews: summary, a section
+/// per category, not populated parts and diagnostics, laid out to be printed
+/// with the project and revision on every page.
+pub struct OutJobReport<W: Write> {
+    wr: W,
+}
+
+impl<W: Write> OutJobReport<W> {
+    pub fn new(wr: W) -> Self {
+        OutJobReport { wr }
+    }
+}
+
+impl<W: Write> OutJob for OutJobReport<W> {
+    fn write(&mut self, data: &ItemsTable, meta: &OutJobMeta) -> Result<()> {
+        let quantity = data.quantity_column();
+        let sections: Vec<ReportSection> = category_groups(&data.rows)
+            .into_iter()
+            .map(|(category, rows)| ReportSection {
+                category: category.trim_matches(|c| c == '*' || c == ' '),
+                parts: rows.iter().map(|r| r.quantity_in(quantity)).sum(),
+                rows,
+            })
+            .collect();
+        let parts = sections.iter().map(|s| s.parts).sum();
+        let html = ReportJob {
+            meta,
+            data,
+            sections,
+            parts,
+            quantity,
+        }
+        .render()?;
+        self.wr.write_all(html.as_bytes())?;
+        self.wr.flush()?;
+        Ok(())
+    }
+}
+
+/// Sheets of the workbook besides the BOM one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct XlsxSheets {
+    /// Project, revision, date, inputs and totals per category, first
+    pub cover: bool,
+    pub dnp: bool,
+    pub diagnostics: bool,
+    /// Rows of each input file and the line they went in
+    pub sources: bool,
+}
+
+impl Default for XlsxSheets {
+    fn default() -> Self {
+        XlsxSheets {
+            cover: false,
+            dnp: true,
+            diagnostics: false,
+            sources: false,
+        }
+    }
+}
+
+impl XlsxSheets {
+    /// Everything the contract manufacturer wants with an order.
+    pub fn all() -> Self {
+        XlsxSheets {
+            cover: true,
+            dnp: true,
+            diagnostics: true,
+            sources: true,
+        }
+    }
+}
+
+/// What the xlsx gets besides the tables: its sheets and style.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct XlsxOptions {
+    pub sheets: XlsxSheets,
+    pub style: XlsxStyle,
+}
 
 pub struct OutJobXlsx {
-    wk: Workbook,
+    path: PathBuf,
+    /// Private directory of an in-memory workbook, removed on drop
+    temp_dir: Option<TempDir>,
+    sheets: XlsxSheets,
+    style: XlsxStyle,
     curr_row: u32,
 }
 
 impl OutJobXlsx {
-    pub fn new<P: AsRef<Path>>(path: P) -> OutJobXlsx {
-        let wk = match Workbook::new(format!("{}.xlsx", path.as_ref().to_str().unwrap()).as_str()) {
-            Ok(wk) => wk,
-            _ => panic!("Unable to add sheet to open wk"),
-        };
+    /// Workbook written to exactly `path` by the write methods.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<OutJobXlsx> {
+        let path = path.as_ref();
+        if path.to_str().is_none() {
+            bail!("Not a valid xlsx path: {}", path.display());
+        }
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            if !dir.is_dir() {
+                bail!("No directory {} for {}", dir.display(), path.display());
+            }
+        }
+        Ok(OutJobXlsx {
+            path: path.to_path_buf(),
+            temp_dir: None,
+            sheets: XlsxSheets::default(),
+            style: XlsxStyle::default(),
+            curr_row: 0,
+        })
+    }
 
-        OutJobXlsx { wk, curr_row: 0 }
+    pub fn with_sheets(mut self, sheets: XlsxSheets) -> Self {
+        self.sheets = sheets;
+        self
+    }
+
+    /// Look of the BOM and DNP tables, the other sheets take its header and
+    /// cell formats.
+    pub fn with_style(mut self, style: XlsxStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn with_options(self, options: &XlsxOptions) -> Self {
+        self.with_sheets(options.sheets)
+            .with_style(options.style.clone())
+    }
+
+    /// Workbook kept in memory, take it with `into_bytes` after writing.
+    /// libxlsxwriter only writes files, so it goes through a temporary one.
+    pub fn in_memory() -> Result<OutJobXlsx> {
+        // Not a shared, guessable name: libxlsxwriter follows symlinks
+        let dir = tempfile::Builder::new()
+            .prefix("mergebom-")
+            .tempdir()
+            .context("Unable to create a temporary directory")?;
+        let mut job = OutJobXlsx::new(dir.path().join("bom.xlsx"))?;
+        job.temp_dir = Some(dir);
+        Ok(job)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Content of the written workbook.
+    pub fn into_bytes(self) -> Result<Vec<u8>> {
+        fs::read(&self.path).with_context(|| format!("Unable to read {}", self.path.display()))
+    }
+
+    fn workbook(&self) -> Result<Workbook> {
+        // Checked by `new`
+        let path = self.path.to_str().unwrap_or_default();
+        Workbook::new(path).with_context(|| format!("Unable to create {}", path))
+    }
+
+    fn close(&self, wk: Workbook) -> Result<()> {
+        wk.close()
+            .with_context(|| format!("Unable to write {}", self.path.display()))
     }
-    pub fn write(mut self, data: &ItemsTable) {
-        let mut fmt_default = Format::new();
-        fmt_default.set_text_wrap();
-        fmt_default.set_font_size(10.0);
-        fmt_default.set_text_wrap();
 
+    /// Colour coded diff sheet: removed lines in red, added in green, changed
+    /// in yellow with "old -> new" in the changed cells, moved designators last.
+    pub fn write_diff(&mut self, diff: &BomDiff) -> Result<()> {
+        self.curr_row = 0;
         let mut fmt_header = Format::new();
         fmt_header.set_bg_color(FormatColor::Cyan);
         fmt_header.set_bold();
         fmt_header.set_font_size(12.0);
 
-        let mut fmt_category = Format::new();
-        fmt_category.set_bg_color(FormatColor::Yellow);
-        fmt_category.set_bold();
-        fmt_category.set_border(FormatBorder::Thin);
-        fmt_category.set_align(FormatAlignment::CenterAcross);
+        let mut fmt_removed = Format::new();
+        fmt_removed.set_bg_color(FormatColor::Red);
+        fmt_removed.set_font_size(10.0);
 
-        let mut fmt_qty = Format::new();
-        fmt_qty.set_bg_color(FormatColor::Lime);
-        fmt_qty.set_bold();
-        fmt_qty.set_font_size(12.0);
+        let mut fmt_added = Format::new();
+        fmt_added.set_bg_color(FormatColor::Lime);
+        fmt_added.set_font_size(10.0);
 
-        let mut sheet = match self.wk.add_worksheet(None) {
-            Ok(wk) => wk,
-            _ => panic!("Unable to add sheet to open wk"),
-        };
+        let mut fmt_changed = Format::new();
+        fmt_changed.set_bg_color(FormatColor::Yellow);
+        fmt_changed.set_font_size(10.0);
+
+        let mut fmt_changed_cell = Format::new();
+        fmt_changed_cell.set_bg_color(FormatColor::Orange);
+        fmt_changed_cell.set_bold();
+        fmt_changed_cell.set_font_size(10.0);
 
-        for (column, hdr) in (0_u16..).zip(data.headers.iter()) {
-            sheet
-                .write_string(self.curr_row, column, hdr, Some(&fmt_header))
-                .unwrap();
+        let wk = self.workbook()?;
+        let mut sheet = wk.add_worksheet(Some("Diff"))?;
+
+        sheet.write_string(self.curr_row, 0, "Change", Some(&fmt_header))?;
+        for (column, hdr) in (1_u16..).zip(diff.headers.iter()) {
+            sheet.write_string(self.curr_row, column, hdr, Some(&fmt_header))?;
         }
         self.curr_row += 1;
-        let mut curr_header = "".to_string();
-        for i in data.rows.iter() {
-            if curr_header != i.category {
-                sheet
-                    .merge_range(
-                        self.curr_row,
-                        0,
-                        self.curr_row,
-                        data.headers.len() as u16,
-                        i.category.as_str(),
-                        Some(&fmt_category),
-                    )
-                    .unwrap();
-                curr_header = i.category.clone();
+
+        for (label, rows, fmt) in [
+            ("Removed", &diff.removed, &fmt_removed),
+            ("Added", &diff.added, &fmt_added),
+        ] {
+            for i in rows.iter() {
+                sheet.write_string(self.curr_row, 0, label, Some(fmt))?;
+                for (column, d) in (1_u16..).zip(i.fields.iter()) {
+                    sheet.write_string(self.curr_row, column, d, Some(fmt))?;
+                }
                 self.curr_row += 1;
             }
-            // Write all fields
-            for (n, d) in i.fields.iter().enumerate() {
-                //debug!("merged {}, np {}", i.is_merged, i.is_np);
-                let mut fmt = Some(&fmt_default);
-                if n == 0 {
-                    fmt = Some(&fmt_qty);
+        }
+
+        for c in diff.changed.iter() {
+            sheet.write_string(self.curr_row, 0, "Changed", Some(&fmt_changed))?;
+            for (column, (hdr, d)) in (1_u16..).zip(diff.headers.iter().zip(c.item.fields.iter())) {
+                let mut value = d.clone();
+                let mut fmt = &fmt_changed;
+                if column == 1 && c.old_quantity != c.new_quantity() {
+                    value = format!("{} -> {}", c.old_quantity, d);
+                    fmt = &fmt_changed_cell;
+                } else if let Some(fc) = c.fields.iter().find(|f| f.header == *hdr) {
+                    value = format!("{} -> {}", fc.old, fc.new);
+                    fmt = &fmt_changed_cell;
+                }
+                sheet.write_string(self.curr_row, column, &value, Some(fmt))?;
+            }
+            self.curr_row += 1;
+        }
+
+        if !diff.moved.is_empty() {
+            self.curr_row += 1;
+            for (column, hdr) in (0_u16..).zip(["Moved", "Designator", "From", "To"]) {
+                sheet.write_string(self.curr_row, column, hdr, Some(&fmt_header))?;
+            }
+            self.curr_row += 1;
+            for m in diff.moved.iter() {
+                for (column, d) in (1_u16..).zip([&m.designator, &m.from, &m.to]) {
+                    sheet.write_string(self.curr_row, column, d, None)?;
                 }
-                sheet.write_string(self.curr_row, n as u16, d, fmt).unwrap();
+                self.curr_row += 1;
+            }
+        }
+        self.close(wk)
+    }
+
+    /// Shortage sheet: every populated line with required, available and
+    /// missing quantities, the lines missing parts in red.
+    pub fn write_shortage(&mut self, report: &ShortageReport) -> Result<()> {
+        self.curr_row = 0;
+        let mut fmt_header = Format::new();
+        fmt_header.set_bg_color(FormatColor::Cyan);
+        fmt_header.set_bold();
+        fmt_header.set_font_size(12.0);
+
+        let mut fmt_default = Format::new();
+        fmt_default.set_text_wrap();
+        fmt_default.set_font_size(10.0);
+
+        let mut fmt_missing = Format::new();
+        fmt_missing.set_text_wrap();
+        fmt_missing.set_bg_color(FormatColor::Red);
+        fmt_missing.set_font_size(10.0);
+
+        let wk = self.workbook()?;
+        let mut sheet = wk.add_worksheet(Some("Shortage"))?;
+
+        sheet.write_string(self.curr_row, 0, "Build quantity", Some(&fmt_header))?;
+        sheet.write_number(
+            self.curr_row,
+            1,
+            report.build_quantity as f64,
+            Some(&fmt_header),
+        )?;
+        self.curr_row += 2;
+
+        for (column, hdr) in (0_u16..).zip([
+            "Designator",
+            "Comment",
+            "Code",
+            "Location",
+            "Required",
+            "Available",
+            "Missing",
+        ]) {
+            sheet.write_string(self.curr_row, column, hdr, Some(&fmt_header))?;
+        }
+        self.curr_row += 1;
+
+        for l in report.lines.iter() {
+            let fmt = if l.missing > 0 {
+                &fmt_missing
+            } else {
+                &fmt_default
+            };
+            let designators = l.designators.join(", ");
+            for (column, d) in (0_u16..).zip([&designators, &l.comment, &l.code, &l.location]) {
+                sheet.write_string(self.curr_row, column, d, Some(fmt))?;
             }
+            for (column, n) in (4_u16..).zip([l.required, l.available, l.missing]) {
+                sheet.write_number(self.curr_row, column, n as f64, Some(fmt))?;
+            }
+            self.curr_row += 1;
+        }
+        self.close(wk)
+    }
 
+    /// A summary sheet with the lines and parts of each assembly step, then
+    /// a sheet per step and one for its not populated parts.
+    pub fn write_splits(&mut self, splits: &[AssemblySplit], meta: &OutJobMeta) -> Result<()> {
+        self.curr_row = 0;
+        let fmts = self.style.formats()?;
+        let wk = self.workbook()?;
+
+        let mut sheet = wk.add_worksheet(Some("Steps"))?;
+        sheet.write_string(self.curr_row, 0, &meta.title, Some(&fmts.header))?;
+        if !meta.revision.is_empty() {
+            sheet.write_string(self.curr_row, 1, &meta.revision, Some(&fmts.header))?;
+        }
+        self.curr_row += 2;
+        for (column, hdr) in (0_u16..).zip(["Step", "Lines", "Parts"]) {
+            sheet.write_string(self.curr_row, column, hdr, Some(&fmts.header))?;
+        }
+        self.curr_row += 1;
+        for split in splits.iter() {
+            sheet.write_string(self.curr_row, 0, split.step.label(), Some(&fmts.default))?;
+            sheet.write_number(self.curr_row, 1, split.lines() as f64, Some(&fmts.default))?;
+            sheet.write_number(self.curr_row, 2, split.parts() as f64, Some(&fmts.default))?;
             self.curr_row += 1;
         }
-        self.wk.close().unwrap();
+        sheet.write_string(self.curr_row, 0, "Total", Some(&fmts.total))?;
+        let lines: usize = splits.iter().map(|s| s.lines()).sum();
+        let parts: usize = splits.iter().map(|s| s.parts()).sum();
+        sheet.write_number(self.curr_row, 1, lines as f64, Some(&fmts.total))?;
+        sheet.write_number(self.curr_row, 2, parts as f64, Some(&fmts.total))?;
+
+        for split in splits.iter() {
+            let table = &split.table;
+            let mut sheet = wk.add_worksheet(Some(split.step.label()))?;
+            write_table(&mut sheet, 0, table, &table.rows, &fmts, &self.style)?;
+            if self.sheets.dnp && !table.dnp.is_empty() {
+                let name = format!("{} DNP", split.step.label());
+                let mut sheet = wk.add_worksheet(Some(&name))?;
+                write_table(&mut sheet, 0, table, &table.dnp, &fmts, &self.style)?;
+            }
+        }
+        self.close(wk)
+    }
+}
+
+impl OutJob for OutJobXlsx {
+    fn write(&mut self, data: &ItemsTable, meta: &OutJobMeta) -> Result<()> {
+        self.curr_row = 0;
+        let wk = self.workbook()?;
+        let fmts = self.style.formats()?;
+
+        if self.sheets.cover {
+            let mut sheet = wk.add_worksheet(Some("Cover"))?;
+            write_cover(&mut sheet, data, meta, &fmts.header, &fmts.default)?;
+        }
+
+        let mut sheet = wk.add_worksheet(Some(MERGED_SHEET))?;
+        // Tells the loader this is ours and where the quantity is, see
+        // `Bom::from_reader`
+        wk.define_name(MERGED_NAME, &format!("={}!$A$1", MERGED_SHEET))?;
+        if let Some(q) = data.quantity_column() {
+            let header = format!("={}!${}$1", MERGED_SHEET, column_name(q));
+            wk.define_name(MERGED_QUANTITY_NAME, &header)?;
+        }
+        self.curr_row = write_table(
+            &mut sheet,
+            self.curr_row,
+            data,
+            &data.rows,
+            &fmts,
+            &self.style,
+        )?;
+
+        // Cost summary under the table, the totals in the extended price
+        // column and their labels on its left
+        if let Some(cost) = &data.cost {
+            let column = data
+                .extended_price_column()
+                .unwrap_or_else(|| data.headers.len().saturating_sub(1))
+                .max(1) as u16
+                - 1;
+            self.curr_row += 1;
+            sheet.write_string(self.curr_row, column, "Build quantity", Some(&fmts.header))?;
+            sheet.write_number(
+                self.curr_row,
+                column + 1,
+                cost.build_quantity as f64,
+                Some(&fmts.header),
+            )?;
+            self.curr_row += 1;
+            for c in cost.categories.iter() {
+                sheet.write_string(self.curr_row, column, &c.category, Some(&fmts.default))?;
+                sheet.write_number(self.curr_row, column + 1, c.total, Some(&fmts.default))?;
+                self.curr_row += 1;
+            }
+            sheet.write_string(
+                self.curr_row,
+                column,
+                &format!("Total {}", cost.currency),
+                Some(&fmts.total),
+            )?;
+            sheet.write_number(self.curr_row, column + 1, cost.total, Some(&fmts.total))?;
+            self.curr_row += 1;
+            if !cost.unpriced.is_empty() {
+                sheet.write_string(
+                    self.curr_row,
+                    column,
+                    &format!("{} lines without price", cost.unpriced.len()),
+                    Some(&fmts.default),
+                )?;
+                self.curr_row += 1;
+            }
+        }
+
+        // Not populated parts go in their own sheet, out of the purchase list
+        if self.sheets.dnp && !data.dnp.is_empty() {
+            let mut sheet = wk.add_worksheet(Some("DNP"))?;
+            write_table(&mut sheet, 0, data, &data.dnp, &fmts, &self.style)?;
+        }
+        if self.sheets.diagnostics {
+            let mut sheet = wk.add_worksheet(Some("Diagnostics"))?;
+            write_rows(
+                &mut sheet,
+                &["Severity", "Line", "Message"],
+                data.diagnostics.iter().map(|d| {
+                    vec![
+                        d.severity.to_string(),
+                        d.unique_id.clone(),
+                        d.message.clone(),
+                    ]
+                }),
+                &fmts.header,
+                &fmts.default,
+            )?;
+        }
+        if self.sheets.sources {
+            let mut sources: Vec<(&Source, &ItemView)> = data
+                .rows
+                .iter()
+                .chain(data.dnp.iter())
+                .flat_map(|r| r.sources.iter().map(move |s| (s, r)))
+                .collect();
+            sources.sort_by(|(a, _), (b, _)| {
+                (&a.file, &a.sheet, a.row).cmp(&(&b.file, &b.sheet, b.row))
+            });
+            let mut sheet = wk.add_worksheet(Some("Sources"))?;
+            write_rows(
+                &mut sheet,
+                &[
+                    "File",
+                    "Sheet",
+                    "Row",
+                    "Designator",
+                    "Quantity",
+                    "Line",
+                    "NP",
+                ],
+                sources.iter().map(|(s, r)| {
+                    vec![
+                        s.file.clone(),
+                        s.sheet.clone(),
+                        s.row.to_string(),
+                        s.designators.join(", "),
+                        s.quantity.to_string(),
+                        r.unique_id.clone(),
+                        if r.is_np { "NP" } else { "" }.to_string(),
+                    ]
+                }),
+                &fmts.header,
+                &fmts.default,
+            )?;
+        }
+        self.close(wk)
+    }
+}
+
+/// Cover sheet: what the workbook was made from, then the lines, parts and
+/// cost of each category.
+fn write_cover(
+    sheet: &mut Worksheet,
+    data: &ItemsTable,
+    meta: &OutJobMeta,
+    fmt_header: &Format,
+    fmt_default: &Format,
+) -> Result<()> {
+    let mut curr_row = 0;
+    for (label, value) in [
+        ("Project", meta.title.as_str()),
+        ("Revision", meta.revision.as_str()),
+        ("Date", meta.date.as_str()),
+        ("Merge keys", &meta.merge_keys.join(", ")),
+    ] {
+        sheet.write_string(curr_row, 0, label, Some(fmt_header))?;
+        sheet.write_string(curr_row, 1, value, Some(fmt_default))?;
+        curr_row += 1;
+    }
+
+    curr_row += 1;
+    sheet.write_string(curr_row, 0, "Input file", Some(fmt_header))?;
+    sheet.write_string(curr_row, 1, "CRC32", Some(fmt_header))?;
+    curr_row += 1;
+    for i in meta.inputs.iter() {
+        sheet.write_string(curr_row, 0, &i.name, Some(fmt_default))?;
+        sheet.write_string(curr_row, 1, &i.crc32, Some(fmt_default))?;
+        curr_row += 1;
+    }
+
+    curr_row += 1;
+    let mut headers = vec![
+        "Category".to_string(),
+        "Lines".to_string(),
+        "Parts".to_string(),
+    ];
+    if let Some(cost) = &data.cost {
+        headers.push(format!("Total {}", cost.currency).trim().to_string());
+    }
+    for (column, hdr) in (0_u16..).zip(headers.iter()) {
+        sheet.write_string(curr_row, column, hdr, Some(fmt_header))?;
+    }
+    curr_row += 1;
+    let (mut lines, mut parts) = (0, 0);
+    let column = data.quantity_column();
+    for (category, rows) in category_groups(&data.rows) {
+        let quantity: usize = rows.iter().map(|r| r.quantity_in(column)).sum();
+        sheet.write_string(curr_row, 0, category, Some(fmt_default))?;
+        sheet.write_number(curr_row, 1, rows.len() as f64, Some(fmt_default))?;
+        sheet.write_number(curr_row, 2, quantity as f64, Some(fmt_default))?;
+        if let Some(c) = data
+            .cost
+            .as_ref()
+            .and_then(|c| c.categories.iter().find(|c| c.category == category))
+        {
+            sheet.write_number(curr_row, 3, c.total, Some(fmt_default))?;
+        }
+        lines += rows.len();
+        parts += quantity;
+        curr_row += 1;
+    }
+    sheet.write_string(curr_row, 0, "Total", Some(fmt_header))?;
+    sheet.write_number(curr_row, 1, lines as f64, Some(fmt_header))?;
+    sheet.write_number(curr_row, 2, parts as f64, Some(fmt_header))?;
+    if let Some(cost) = &data.cost {
+        sheet.write_number(curr_row, 3, cost.total, Some(fmt_header))?;
+    }
+    Ok(())
+}
+
+/// Plain sheet: a header line and the rows under it.
+fn write_rows<I: Iterator<Item = Vec<String>>>(
+    sheet: &mut Worksheet,
+    headers: &[&str],
+    rows: I,
+    fmt_header: &Format,
+    fmt_default: &Format,
+) -> Result<()> {
+    for (column, hdr) in (0_u16..).zip(headers.iter()) {
+        sheet.write_string(0, column, hdr, Some(fmt_header))?;
+    }
+    for (row, fields) in (1_u32..).zip(rows) {
+        for (column, d) in (0_u16..).zip(fields.iter()) {
+            sheet.write_string(row, column, d, Some(fmt_default))?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the headers of `data` and `rows`, its lines or its not populated
+/// ones, starting from `curr_row`, grouped by category as `style` says.
+/// Return the next free row.
+fn write_table(
+    sheet: &mut Worksheet,
+    mut curr_row: u32,
+    data: &ItemsTable,
+    rows: &[ItemView],
+    fmts: &TableFormats,
+    style: &XlsxStyle,
+) -> Result<u32> {
+    let headers = &data.headers;
+    let header_row = curr_row;
+    for (column, hdr) in (0_u16..).zip(headers.iter()) {
+        sheet.write_string(curr_row, column, hdr, Some(&fmts.header))?;
+    }
+    curr_row += 1;
+    let last_column = headers.len().saturating_sub(1) as u16;
+    let quantity = data.quantity_column();
+    if style.outline {
+        // Expand buttons on the category rows
+        sheet.outline_settings(true, false, false, false);
+    }
+
+    let groups = if style.category_rows {
+        category_groups(rows)
+    } else {
+        vec![("", rows.iter().collect())]
+    };
+    for (category, group) in groups {
+        if style.category_rows {
+            if last_column > 0 {
+                sheet.merge_range(
+                    curr_row,
+                    0,
+                    curr_row,
+                    last_column,
+                    category,
+                    Some(&fmts.category),
+                )?;
+            } else {
+                sheet.write_string(curr_row, 0, category, Some(&fmts.category))?;
+            }
+            curr_row += 1;
+        }
+        let mut parts = 0;
+        for i in group.iter() {
+            let fmt_row = fmts.row(i);
+            parts += i.quantity_in(quantity);
+            for (n, d) in i.fields.iter().enumerate() {
+                let column = n as u16;
+                if Some(n) == quantity {
+                    match d.parse::<f64>() {
+                        Ok(q) if fmts.quantity_is_number => {
+                            sheet.write_number(curr_row, column, q, Some(&fmts.quantity))?
+                        }
+                        _ => sheet.write_string(curr_row, column, d, Some(&fmts.quantity))?,
+                    }
+                    continue;
+                }
+                sheet.write_string(curr_row, column, d, Some(fmt_row))?;
+            }
+            if style.outline && style.category_rows {
+                let level = RowColOptions::new(false, 1, false);
+                sheet.set_row_opt(curr_row, LXW_DEF_ROW_HEIGHT, None, &level)?;
+            }
+            curr_row += 1;
+        }
+        if style.subtotals {
+            // Parts under the quantity, lines in the first other column
+            let lines_column = match quantity {
+                Some(0) => 1,
+                _ => 0,
+            };
+            if let Some(q) = quantity {
+                sheet.write_number(curr_row, q as u16, parts as f64, Some(&fmts.total))?;
+            }
+            if lines_column <= last_column {
+                let lines = match group.len() {
+                    1 => "1 line".to_string(),
+                    n => format!("{} lines", n),
+                };
+                sheet.write_string(curr_row, lines_column, &lines, Some(&fmts.total))?;
+            }
+            curr_row += 1;
+        }
+    }
+
+    for (column, width) in (0_u16..).zip(style.column_widths(headers, rows)) {
+        if let Some(width) = width {
+            sheet.set_column(column, column, width, None)?;
+        }
+    }
+    if style.freeze_header {
+        sheet.freeze_panes(header_row + 1, 0);
+    }
+    if style.autofilter && !headers.is_empty() {
+        sheet.autofilter(
+            header_row,
+            0,
+            curr_row.saturating_sub(1).max(header_row),
+            headers.len() as u16 - 1,
+        )?;
     }
+    Ok(curr_row)
 }
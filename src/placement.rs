@@ -0,0 +1,383 @@
+use anyhow::{bail, Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use super::bom::{Bom, Diagnostic, Layer, Severity};
+use super::utils::{natural_cmp, read_sheet_rows};
+
+/// Where a part goes on the board, positions in mm and rotation in degrees.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Placement {
+    pub designator: String,
+    #[serde(default)]
+    pub value: String,
+    #[serde(default)]
+    pub footprint: String,
+    #[serde(default)]
+    pub mpn: String,
+    pub x: f64,
+    pub y: f64,
+    #[serde(default)]
+    pub rotation: f64,
+    pub side: Layer,
+}
+
+/// Placements of pick-and-place files, one per designator.
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
+pub struct Placements {
+    placements: Vec<Placement>,
+    #[serde(skip)]
+    by_designator: HashMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum PlacementColumn {
+    Designator,
+    Value,
+    Footprint,
+    X,
+    Y,
+    Rotation,
+    Side,
+}
+
+/// Factor to mm of a unit name, None if unknown.
+fn unit_scale(unit: &str) -> Option<f64> {
+    match unit.trim().to_lowercase().as_str() {
+        "mm" => Some(1.0),
+        "mil" | "mils" => Some(0.0254),
+        "in" | "inch" | "inches" => Some(25.4),
+        _ => None,
+    }
+}
+
+/// Column of a header and the factor to mm of its unit if it has one, eg.
+/// "Center-X(mm)" for Altium or "PosX" for KiCad.
+fn placement_column(header: &str) -> Option<(PlacementColumn, Option<f64>)> {
+    let header = header.trim().to_lowercase();
+    let (name, scale) = match header.split_once('(') {
+        Some((name, unit)) => (name.trim(), unit_scale(unit.trim_end_matches(')'))),
+        None => (header.as_str(), None),
+    };
+    let column = match name {
+        "designator" | "ref" | "refdes" | "reference" => PlacementColumn::Designator,
+        "comment" | "val" | "value" => PlacementColumn::Value,
+        "footprint" | "package" => PlacementColumn::Footprint,
+        "center-x" | "center x" | "mid x" | "posx" | "pos x" | "x" => PlacementColumn::X,
+        "center-y" | "center y" | "mid y" | "posy" | "pos y" | "y" => PlacementColumn::Y,
+        "rotation" | "rot" | "angle" => PlacementColumn::Rotation,
+        "layer" | "side" | "tb" => PlacementColumn::Side,
+        _ => return None,
+    };
+    Some((column, scale))
+}
+
+/// Number of a cell, "12.5mm" and "12,5" are 12.5.
+fn parse_number(cell: &str) -> Option<f64> {
+    let cell = cell
+        .trim()
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+        .replace(',', ".");
+    cell.parse().ok()
+}
+
+/// Factor to mm of a KiCad "## Unit = inches, Angle = deg." line.
+fn kicad_unit(line: &str) -> Option<f64> {
+    line.trim_start_matches('#')
+        .split(',')
+        .filter_map(|p| p.split_once('='))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("unit"))
+        .and_then(|(_, unit)| unit_scale(unit.trim_end_matches('.')))
+}
+
+/// Split a text line on blanks, a quoted cell can have blanks.
+fn split_text_line(line: &str) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !cell.is_empty() {
+                    cells.push(std::mem::take(&mut cell));
+                }
+            }
+            c => cell.push(c),
+        }
+    }
+    if !cell.is_empty() {
+        cells.push(cell);
+    }
+    cells
+}
+
+impl Placements {
+    pub fn new() -> Placements {
+        Placements::default()
+    }
+
+    pub fn placements(&self) -> &[Placement] {
+        &self.placements
+    }
+
+    pub fn len(&self) -> usize {
+        self.placements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.placements.is_empty()
+    }
+
+    pub fn get(&self, designator: &str) -> Option<&Placement> {
+        self.by_designator
+            .get(designator)
+            .map(|i| &self.placements[*i])
+    }
+
+    /// Add a placement, false if the designator is already placed.
+    pub fn add(&mut self, placement: Placement) -> bool {
+        if self.by_designator.contains_key(&placement.designator) {
+            return false;
+        }
+        self.by_designator
+            .insert(placement.designator.clone(), self.placements.len());
+        self.placements.push(placement);
+        true
+    }
+
+    /// Placements of one side of the board.
+    pub fn side(&self, side: Layer) -> impl Iterator<Item = &Placement> {
+        self.placements.iter().filter(move |p| p.side == side)
+    }
+
+    /// Load an Altium pick-and-place file (csv, or txt with quoted cells) or
+    /// a KiCad one (pos, or csv).
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Placements> {
+        let path = path.as_ref();
+        let text = matches!(
+            path.extension()
+                .and_then(OsStr::to_str)
+                .map(|e| e.to_lowercase())
+                .as_deref(),
+            Some("pos" | "txt")
+        );
+        let mut scale = 1.0;
+        let rows = if text {
+            let data = fs::read_to_string(path)
+                .with_context(|| format!("Unable to read {}", path.display()))?;
+            let mut rows = Vec::new();
+            for l in data.lines() {
+                match l.strip_prefix("##") {
+                    Some(comment) => scale = kicad_unit(comment).unwrap_or(scale),
+                    None => rows.push(split_text_line(l.trim_start_matches('#'))),
+                }
+            }
+            rows
+        } else {
+            read_sheet_rows(path)?
+        };
+        Placements::from_rows_in(&rows, scale).with_context(|| format!("{}", path.display()))
+    }
+
+    /// Placements from spreadsheet rows, everything before the header row is
+    /// skipped. Parts without a side column are on top, a part whose side is
+    /// not understood is left out with a warning.
+    pub fn from_rows(rows: &[Vec<String>]) -> Result<Placements> {
+        Placements::from_rows_in(rows, 1.0)
+    }
+
+    /// Like `from_rows`, positions in columns without a unit are scaled to
+    /// mm by `scale`.
+    fn from_rows_in(rows: &[Vec<String>], scale: f64) -> Result<Placements> {
+        let mut columns: HashMap<PlacementColumn, (usize, f64)> = HashMap::new();
+        let mut placements = Placements::new();
+        for row in rows.iter() {
+            if columns.is_empty() {
+                for (i, c) in row.iter().enumerate() {
+                    if let Some((col, unit)) = placement_column(c) {
+                        columns.entry(col).or_insert((i, unit.unwrap_or(scale)));
+                    }
+                }
+                let needed = [
+                    PlacementColumn::Designator,
+                    PlacementColumn::X,
+                    PlacementColumn::Y,
+                ];
+                if !needed.iter().all(|c| columns.contains_key(c)) {
+                    columns.clear();
+                }
+                continue;
+            }
+
+            let cell = |col| {
+                columns
+                    .get(&col)
+                    .and_then(|(i, _)| row.get(*i))
+                    .map_or("", |s| s.trim())
+            };
+            let number = |col| {
+                let scale = columns.get(&col).map_or(1.0, |(_, s)| *s);
+                parse_number(cell(col)).map(|n| n * scale)
+            };
+            let designator = cell(PlacementColumn::Designator);
+            let (x, y) = match (number(PlacementColumn::X), number(PlacementColumn::Y)) {
+                (Some(x), Some(y)) if !designator.is_empty() => (x, y),
+                _ => continue,
+            };
+            let side = match columns.contains_key(&PlacementColumn::Side) {
+                false => Layer::Top,
+                true => match cell(PlacementColumn::Side).parse() {
+                    Ok(side) => side,
+                    Err(_) => {
+                        warn!(
+                            "{}: unknown side {:?}, skip it",
+                            designator,
+                            cell(PlacementColumn::Side)
+                        );
+                        continue;
+                    }
+                },
+            };
+            let placement = Placement {
+                designator: designator.to_string(),
+                value: cell(PlacementColumn::Value).to_string(),
+                footprint: cell(PlacementColumn::Footprint).to_string(),
+                mpn: String::new(),
+                x,
+                y,
+                rotation: parse_number(cell(PlacementColumn::Rotation)).unwrap_or(0.0),
+                side,
+            };
+            if !placements.add(placement) {
+                warn!("{} placed twice, the first one is kept", designator);
+            }
+        }
+        if columns.is_empty() {
+            bail!("No pick-and-place header: Designator, X and Y columns are needed");
+        }
+        Ok(placements)
+    }
+
+    /// Write the placements of `side` as csv, in designator order. Return
+    /// the number of parts.
+    pub fn write_csv<W: Write>(&self, side: Layer, wr: W) -> Result<usize> {
+        let mut placements: Vec<&Placement> = self.side(side).collect();
+        placements.sort_by(|a, b| natural_cmp(&a.designator, &b.designator));
+
+        let mut wr = csv::Writer::from_writer(wr);
+        wr.write_record([
+            "Designator",
+            "Value",
+            "Footprint",
+            "MPN",
+            "X (mm)",
+            "Y (mm)",
+            "Rotation",
+            "Side",
+        ])?;
+        for p in placements.iter() {
+            wr.write_record([
+                p.designator.clone(),
+                p.value.clone(),
+                p.footprint.clone(),
+                p.mpn.clone(),
+                format!("{:.4}", p.x),
+                format!("{:.4}", p.y),
+                format!("{:.2}", p.rotation),
+                p.side.to_string(),
+            ])?;
+        }
+        wr.flush()?;
+        Ok(placements.len())
+    }
+}
+
+impl Bom {
+    /// Compare the pick-and-place data to the BOM: populated parts not
+    /// placed, not populated parts placed, placements of unknown parts and
+    /// parts on the other side than their "Layer". Call it on the merged BOM.
+    pub fn check_placements(&self, placements: &Placements) -> Vec<Diagnostic> {
+        let mut diags = Vec::new();
+        let mut known = HashSet::new();
+        for item in self.items() {
+            let mut missing = Vec::new();
+            for d in item.designators() {
+                known.insert(d.as_str());
+                let placement = match placements.get(d) {
+                    Some(p) => p,
+                    None => {
+                        if !item.is_np() {
+                            missing.push(d.as_str());
+                        }
+                        continue;
+                    }
+                };
+                if item.is_np() {
+                    diags.push(Diagnostic {
+                        unique_id: item.unique_id().to_string(),
+                        severity: Severity::Warning,
+                        message: format!("{}: placed but not populated", d),
+                    });
+                }
+                match item.layer() {
+                    Some(layer) if layer != placement.side => diags.push(Diagnostic {
+                        unique_id: item.unique_id().to_string(),
+                        severity: Severity::Error,
+                        message: format!(
+                            "{}: {} in the BOM, {} in the placement",
+                            d, layer, placement.side
+                        ),
+                    }),
+                    _ => (),
+                }
+            }
+            if !missing.is_empty() {
+                diags.push(Diagnostic {
+                    unique_id: item.unique_id().to_string(),
+                    severity: Severity::Error,
+                    message: format!("{}: no placement", missing.join(", ")),
+                });
+            }
+        }
+        for p in placements.placements() {
+            if !known.contains(p.designator.as_str()) {
+                diags.push(Diagnostic {
+                    unique_id: p.designator.clone(),
+                    severity: Severity::Warning,
+                    message: format!("{}: placed but not in the BOM", p.designator),
+                });
+            }
+        }
+        diags
+    }
+
+    /// Placements of the populated parts with the value, footprint and MPN
+    /// of the BOM. Call it on the merged BOM.
+    pub fn centroid(&self, placements: &Placements) -> Placements {
+        let mut centroid = Placements::new();
+        for item in self.items().filter(|i| !i.is_np()) {
+            for d in item.designators() {
+                if let Some(p) = placements.get(d) {
+                    let fill = |bom: Option<&str>, pnp: &str| {
+                        bom.filter(|s| !s.trim().is_empty())
+                            .unwrap_or(pnp)
+                            .to_string()
+                    };
+                    centroid.add(Placement {
+                        value: fill(item.comment(), &p.value),
+                        footprint: fill(item.footprint(), &p.footprint),
+                        mpn: fill(item.mpn(), &p.mpn),
+                        ..p.clone()
+                    });
+                }
+            }
+        }
+        centroid
+    }
+}
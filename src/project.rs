@@ -0,0 +1,270 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Local;
+use log::info;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::attrition::AttritionRules;
+use super::bom::{Bom, ItemsTable, SortBy, Variant};
+use super::columns::ColumnSpec;
+use super::cost::PriceList;
+use super::library::PartsLibrary;
+use super::outjob::{InputFile, OutJobMeta};
+
+/// Version written in new project files. Bump it when the layout changes and
+/// teach `migrate` how to bring the previous one up to date.
+pub const PROJECT_VERSION: u32 = 1;
+
+/// Options of a merge, everything but the inputs and the merge keys.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ProjectOptions {
+    #[serde(default)]
+    pub sort_by: SortBy,
+    #[serde(default)]
+    pub source_columns: bool,
+    #[serde(default)]
+    pub variants: Vec<Variant>,
+    /// Price list (csv or xlsx) to cost the merged BOM with
+    #[serde(default)]
+    pub price_list: Option<PathBuf>,
+    /// Boards to build, for the extended prices; 0 is taken as 1
+    #[serde(default)]
+    pub build_quantity: usize,
+    /// Parts library (json) filling the blank fields of the inputs
+    #[serde(default)]
+    pub library: Option<PathBuf>,
+    /// Spare parts added to the purchase quantities
+    #[serde(default)]
+    pub attrition: AttritionRules,
+    /// Board revision, shown in the outputs
+    #[serde(default)]
+    pub revision: String,
+    /// Columns of the outputs, all of them if empty
+    #[serde(default)]
+    pub columns: ColumnSpec,
+}
+
+/// A merge saved on disk: what was merged, how, and the result, so it can be
+/// run again or inspected later without the original command line.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MergeProject {
+    pub version: u32,
+    pub inputs: Vec<PathBuf>,
+    pub merge_keys: Vec<String>,
+    #[serde(default)]
+    pub options: ProjectOptions,
+    /// Merged BOM of the last run, `None` until the project is run.
+    #[serde(default)]
+    pub result: Option<Bom>,
+}
+
+/// Project file format, chosen by the file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectFormat {
+    Json,
+    Toml,
+}
+
+impl ProjectFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<ProjectFormat> {
+        match path
+            .as_ref()
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(|e| e.to_lowercase())
+            .as_deref()
+        {
+            Some("json") => Some(ProjectFormat::Json),
+            Some("toml") => Some(ProjectFormat::Toml),
+            _ => None,
+        }
+    }
+}
+
+/// Load a json or toml configuration file, by extension; `what` names it in
+/// the errors.
+pub(crate) fn load_config<T: DeserializeOwned, P: AsRef<Path>>(path: P, what: &str) -> Result<T> {
+    let path = path.as_ref();
+    let data =
+        fs::read_to_string(path).with_context(|| format!("Unable to read {}", path.display()))?;
+    let value = match ProjectFormat::from_path(path) {
+        Some(ProjectFormat::Json) => serde_json::from_str(&data)?,
+        Some(ProjectFormat::Toml) => toml::from_str(&data)?,
+        None => bail!("{}: {} are .json or .toml", path.display(), what),
+    };
+    Ok(value)
+}
+
+impl MergeProject {
+    pub fn new<P: AsRef<Path>>(inputs: &[P], merge_keys: &[String]) -> MergeProject {
+        MergeProject {
+            version: PROJECT_VERSION,
+            inputs: inputs.iter().map(|p| p.as_ref().to_path_buf()).collect(),
+            merge_keys: merge_keys.to_vec(),
+            options: ProjectOptions::default(),
+            result: None,
+        }
+    }
+
+    /// Load the inputs again and merge them, the result replaces the saved one.
+    /// Fails if an input has moved or is not a csv, xlsx or xls file.
+    pub fn run(&mut self) -> Result<ItemsTable> {
+        for path in self.inputs.iter() {
+            match path.extension().and_then(OsStr::to_str) {
+                Some("csv" | "xlsx" | "xls") => (),
+                _ => bail!("{}: inputs are .csv, .xlsx or .xls", path.display()),
+            }
+            fs::File::open(path)
+                .with_context(|| format!("Unable to read input {}", path.display()))?;
+        }
+        let mut bom = Bom::loader(self.inputs.as_slice(), &self.merge_keys);
+        if let Some(path) = &self.options.library {
+            bom.enrich(&PartsLibrary::load(path)?);
+        }
+        for v in self.options.variants.iter() {
+            bom.add_variant(v.clone());
+        }
+        self.result = Some(bom.merge());
+        self.table()
+    }
+
+    /// Table of the saved result, with the project options.
+    pub fn table(&self) -> Result<ItemsTable> {
+        let bom = match &self.result {
+            Some(bom) => bom,
+            None => bail!("The project has no merged result, run it first"),
+        };
+        let mut data = bom.variants_table(self.options.sort_by)?;
+        if let Some(path) = &self.options.library {
+            data.diagnostics
+                .extend(PartsLibrary::load(path)?.unmatched(bom));
+        }
+        if !self.options.attrition.is_empty() {
+            data.add_attrition(&self.options.attrition, self.options.build_quantity.max(1));
+        }
+        if let Some(path) = &self.options.price_list {
+            let prices = PriceList::load(path)?;
+            data.add_costs(&prices, self.options.build_quantity.max(1));
+        }
+        if self.options.source_columns {
+            data.add_source_columns();
+        }
+        Ok(data)
+    }
+
+    /// What the outputs of the project were made from, under `title`, with
+    /// today as date.
+    pub fn out_job_meta(&self, title: &str) -> OutJobMeta {
+        OutJobMeta {
+            title: title.to_string(),
+            revision: self.options.revision.clone(),
+            date: Local::now().format("%Y-%m-%d").to_string(),
+            inputs: self
+                .inputs
+                .iter()
+                .map(|p| InputFile {
+                    name: p
+                        .file_name()
+                        .map_or(p.display().to_string(), |n| n.to_string_lossy().to_string()),
+                    crc32: fs::read(p)
+                        .map(|data| format!("{:08x}", crc32fast::hash(&data)))
+                        .unwrap_or_default(),
+                })
+                .collect(),
+            merge_keys: self.merge_keys.clone(),
+        }
+    }
+
+    pub fn to_string(&self, format: ProjectFormat) -> Result<String> {
+        Ok(match format {
+            ProjectFormat::Json => serde_json::to_string_pretty(self)?,
+            // toml has no null and no enum with data: go through a json value,
+            // where `Field::List(..)` is already a `{ List = [..] }` table.
+            ProjectFormat::Toml => {
+                let mut value = serde_json::to_value(self)?;
+                strip_nulls(&mut value);
+                toml::to_string_pretty(&toml::Value::try_from(value)?)?
+            }
+        })
+    }
+
+    pub fn from_str(data: &str, format: ProjectFormat) -> Result<MergeProject> {
+        let value: serde_json::Value = match format {
+            ProjectFormat::Json => serde_json::from_str(data)?,
+            ProjectFormat::Toml => toml::from_str::<toml::Value>(data)?.try_into()?,
+        };
+        Ok(serde_json::from_value(migrate(value)?)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let format = ProjectFormat::from_path(path)
+            .ok_or_else(|| anyhow!("{}: project files are .json or .toml", path.display()))?;
+        fs::write(path, self.to_string(format)?)
+            .with_context(|| format!("Unable to write {}", path.display()))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<MergeProject> {
+        let path = path.as_ref();
+        let format = ProjectFormat::from_path(path)
+            .ok_or_else(|| anyhow!("{}: project files are .json or .toml", path.display()))?;
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+        MergeProject::from_str(&data, format).with_context(|| format!("{}", path.display()))
+    }
+}
+
+fn strip_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, v| !v.is_null());
+            map.values_mut().for_each(strip_nulls);
+        }
+        serde_json::Value::Array(list) => list.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+/// Bring a project of any known version to `PROJECT_VERSION`.
+///
+/// Version 0 has no `version` field: it is a bare serialized `Bom`, as saved
+/// before project files existed. It becomes the result of a project without
+/// inputs, so it can be inspected but not run again.
+fn migrate(mut value: serde_json::Value) -> Result<serde_json::Value> {
+    let version = match value.get("version") {
+        Some(v) => v
+            .as_u64()
+            .ok_or_else(|| anyhow!("Invalid project version: {}", v))?,
+        None => 0,
+    };
+    if version > PROJECT_VERSION as u64 {
+        bail!(
+            "Project version {} is newer than the supported {}",
+            version,
+            PROJECT_VERSION
+        );
+    }
+
+    if version == 0 {
+        if value.get("items").is_none() {
+            bail!("Not a merge project: no version and no items");
+        }
+        info!("Migrate project from version 0");
+        let merge_keys = value
+            .get("merge_keys")
+            .cloned()
+            .unwrap_or_else(|| serde_json::json!([]));
+        value = serde_json::json!({
+            "version": 1,
+            "inputs": [],
+            "merge_keys": merge_keys,
+            "result": value,
+        });
+    }
+
+    Ok(value)
+}
@@ -0,0 +1,223 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use xlsxwriter::prelude::{FormatAlignment, FormatBorder, FormatColor};
+use xlsxwriter::Format;
+
+use super::bom::{category_is, ItemView};
+use super::project::load_config;
+
+/// Look of the xlsx tables, the default is the historical one. Colours are
+/// names ("cyan", "lime", ..) or "#RRGGBB".
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct XlsxStyle {
+    pub font_size: f64,
+    pub header_font_size: f64,
+    pub text_wrap: bool,
+    pub header_color: String,
+    pub category_color: String,
+    pub quantity_color: String,
+    /// Background of the rows of a category, by name or letter: "C" = "#DDEEFF"
+    pub category_colors: BTreeMap<String, String>,
+    /// Background of the rows merged from several lines
+    pub merged_color: Option<String>,
+    /// Background of the not populated rows
+    pub np_color: Option<String>,
+    /// Excel number format of the quantity column, eg. "0"; text if empty
+    pub quantity_format: String,
+    /// Width of the columns by header label
+    pub column_widths: BTreeMap<String, f64>,
+    /// Width of the other columns from their content
+    pub auto_fit: bool,
+    pub freeze_header: bool,
+    pub autofilter: bool,
+    /// A title row before the lines of each category
+    pub category_rows: bool,
+    /// A row after each category with its parts and lines
+    pub subtotals: bool,
+    /// Collapsible categories, with `category_rows`
+    pub outline: bool,
+}
+
+impl Default for XlsxStyle {
+    fn default() -> Self {
+        XlsxStyle {
+            font_size: 10.0,
+            header_font_size: 12.0,
+            text_wrap: true,
+            header_color: "cyan".to_string(),
+            category_color: "yellow".to_string(),
+            quantity_color: "lime".to_string(),
+            category_colors: BTreeMap::new(),
+            merged_color: None,
+            np_color: None,
+            quantity_format: String::new(),
+            column_widths: BTreeMap::new(),
+            auto_fit: false,
+            freeze_header: false,
+            autofilter: false,
+            category_rows: true,
+            subtotals: false,
+            outline: false,
+        }
+    }
+}
+
+fn color(name: &str) -> Result<FormatColor> {
+    let name = name.trim().to_lowercase();
+    if let Some(hex) = name.strip_prefix('#') {
+        return u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6)
+            .map(FormatColor::Custom)
+            .ok_or_else(|| anyhow!("Invalid colour {}", name));
+    }
+    Ok(match name.as_str() {
+        "black" => FormatColor::Black,
+        "blue" => FormatColor::Blue,
+        "brown" => FormatColor::Brown,
+        "cyan" => FormatColor::Cyan,
+        "gray" | "grey" => FormatColor::Gray,
+        "green" => FormatColor::Green,
+        "lime" => FormatColor::Lime,
+        "magenta" => FormatColor::Magenta,
+        "navy" => FormatColor::Navy,
+        "orange" => FormatColor::Orange,
+        "purple" => FormatColor::Purple,
+        "red" => FormatColor::Red,
+        "pink" => FormatColor::Pink,
+        "silver" => FormatColor::Silver,
+        "white" => FormatColor::White,
+        "yellow" => FormatColor::Yellow,
+        _ => bail!("Invalid colour {}", name),
+    })
+}
+
+/// Formats of a table built from a style.
+pub(crate) struct TableFormats {
+    pub header: Format,
+    pub category: Format,
+    pub quantity: Format,
+    /// Like `quantity`, for the totals
+    pub total: Format,
+    pub default: Format,
+    merged: Option<Format>,
+    np: Option<Format>,
+    categories: Vec<(String, Format)>,
+    pub quantity_is_number: bool,
+}
+
+impl TableFormats {
+    /// Format of the cells of `row` but the quantity.
+    pub fn row(&self, row: &ItemView) -> &Format {
+        let by_category = || {
+            self.categories
+                .iter()
+                .find(|(name, _)| category_is(&row.category, name))
+                .map(|(_, f)| f)
+        };
+        let np = self.np.as_ref().filter(|_| row.is_np);
+        let merged = self.merged.as_ref().filter(|_| row.is_merged);
+        np.or(merged).or_else(by_category).unwrap_or(&self.default)
+    }
+}
+
+impl XlsxStyle {
+    /// Load a style from json or toml, by extension. The colours are checked.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<XlsxStyle> {
+        let path = path.as_ref();
+        let style: XlsxStyle = load_config(path, "styles")?;
+        style
+            .formats()
+            .with_context(|| format!("{}", path.display()))?;
+        Ok(style)
+    }
+
+    fn cell_format(&self, background: Option<&str>) -> Result<Format> {
+        let mut fmt = Format::new();
+        fmt.set_font_size(self.font_size);
+        if self.text_wrap {
+            fmt.set_text_wrap();
+        }
+        if let Some(bg) = background {
+            fmt.set_bg_color(color(bg)?);
+        }
+        Ok(fmt)
+    }
+
+    pub(crate) fn formats(&self) -> Result<TableFormats> {
+        let mut header = Format::new();
+        header.set_bg_color(color(&self.header_color)?);
+        header.set_bold();
+        header.set_font_size(self.header_font_size);
+
+        let mut category = Format::new();
+        category.set_bg_color(color(&self.category_color)?);
+        category.set_bold();
+        category.set_border(FormatBorder::Thin);
+        category.set_align(FormatAlignment::CenterAcross);
+
+        let mut total = Format::new();
+        total.set_bg_color(color(&self.quantity_color)?);
+        total.set_bold();
+        total.set_font_size(self.header_font_size);
+
+        let mut quantity = Format::new();
+        quantity.set_bg_color(color(&self.quantity_color)?);
+        quantity.set_bold();
+        quantity.set_font_size(self.header_font_size);
+        if !self.quantity_format.is_empty() {
+            quantity.set_num_format(&self.quantity_format);
+        }
+
+        let optional = |c: &Option<String>| -> Result<Option<Format>> {
+            c.as_deref().map(|c| self.cell_format(Some(c))).transpose()
+        };
+        Ok(TableFormats {
+            header,
+            category,
+            quantity,
+            total,
+            default: self.cell_format(None)?,
+            merged: optional(&self.merged_color)?,
+            np: optional(&self.np_color)?,
+            categories: self
+                .category_colors
+                .iter()
+                .map(|(name, c)| Ok((name.clone(), self.cell_format(Some(c))?)))
+                .collect::<Result<_>>()?,
+            quantity_is_number: !self.quantity_format.is_empty(),
+        })
+    }
+
+    /// Width of each column: the configured one, from the content with
+    /// `auto_fit`, or None to keep the Excel default.
+    pub fn column_widths(&self, headers: &[String], rows: &[ItemView]) -> Vec<Option<f64>> {
+        headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| {
+                let configured = self
+                    .column_widths
+                    .iter()
+                    .find(|(label, _)| label.eq_ignore_ascii_case(h))
+                    .map(|(_, w)| *w);
+                configured.or_else(|| {
+                    self.auto_fit.then(|| {
+                        let longest = rows
+                            .iter()
+                            .filter_map(|r| r.fields.get(i))
+                            .chain(std::iter::once(h))
+                            .flat_map(|f| f.lines())
+                            .map(|l| l.chars().count())
+                            .max()
+                            .unwrap_or(0);
+                        (longest as f64 + 2.0).clamp(6.0, 60.0)
+                    })
+                })
+            })
+            .collect()
+    }
+}
@@ -1,5 +1,21 @@
+use anyhow::{bail, Result};
+use calamine::{open_workbook_auto, DataType, Reader};
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::cmp::Ordering;
+use std::path::Path;
+
+use crate::bom::BomFormat;
+
+pub fn is_dnp_marker(text: &str) -> bool {
+    lazy_static! {
+        static ref DNP: Regex = Regex::new(
+            r"(?i)^\s*(NP|DNP|DNF|DNI|NOT\s+FITTED|NOT\s+POPULATED|DO\s+NOT\s+(FIT|POPULATE|PLACE))\b"
+        )
+        .unwrap();
+    }
+    DNP.is_match(text)
+}
 
 pub fn detect_measure_unit(comment: &str) -> String {
     lazy_static! {
@@ -55,8 +71,15 @@ pub fn value_to_eng_notation(base: f32, exp: i32, unit: &str) -> String {
 }
 
 pub fn convert_comment_to_value(comment: &str) -> (f32, i32) {
-    if comment == "NP" {
-        return (-1.0, 0);
+    match try_convert_comment_to_value(comment) {
+        Ok(v) => v,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+pub fn try_convert_comment_to_value(comment: &str) -> Result<(f32, i32)> {
+    if is_dnp_marker(comment) {
+        return Ok((-1.0, 0));
     }
 
     let v = comment
@@ -65,7 +88,7 @@ pub fn convert_comment_to_value(comment: &str) -> (f32, i32) {
         .collect::<Vec<_>>();
 
     let value = match v.first() {
-        None => panic!("No component value to parse"),
+        None => bail!("No component value to parse"),
         Some(v) => v,
     };
 
@@ -74,7 +97,7 @@ pub fn convert_comment_to_value(comment: &str) -> (f32, i32) {
     }
 
     match VAL.captures(value) {
-        None => panic!("Fail to parse component value"),
+        None => bail!("Fail to parse component value"),
         Some(cc) => {
             let left = cc.get(1).map_or("", |m| m.as_str());
             let mult = match cc.get(2).map_or("", |m| m.as_str()) {
@@ -86,7 +109,7 @@ pub fn convert_comment_to_value(comment: &str) -> (f32, i32) {
                 "u" => -6,
                 "n" => -9,
                 "p" => -12,
-                _ => panic!("Invalid number"),
+                _ => bail!("Invalid number"),
             };
             let right = cc.get(3).map_or("", |m| m.as_str());
 
@@ -99,18 +122,135 @@ pub fn convert_comment_to_value(comment: &str) -> (f32, i32) {
             }
 
             let base = match together.parse::<f32>() {
-                Err(error) => panic!(
+                Err(error) => bail!(
                     "Invalid base number for convertion from string value to float {:?}",
                     error
                 ),
                 Ok(v) => v,
             };
 
-            (base, mult)
+            Ok((base, mult))
+        }
+    }
+}
+
+/// Component value of a comment as a plain number ("2k2" -> 2200.0), None
+/// when the comment has no numeric value or is not populated.
+pub fn comment_to_number(comment: &str) -> Option<f64> {
+    let first = comment.split(',').next().unwrap_or("").trim();
+    if !first.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    match try_convert_comment_to_value(first) {
+        Ok((base, _)) if base < 0.0 => None,
+        Ok((base, 1)) => Some(base as f64),
+        Ok((base, exp)) => Some(base as f64 * 10_f64.powi(exp)),
+        Err(_) => None,
+    }
+}
+
+/// Compare strings with the digit runs taken as numbers, so "R2" < "R10".
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut na = String::new();
+                while let Some(c) = a.next_if(|c| c.is_ascii_digit()) {
+                    na.push(c);
+                }
+                let mut nb = String::new();
+                while let Some(c) = b.next_if(|c| c.is_ascii_digit()) {
+                    nb.push(c);
+                }
+                let na = na.trim_start_matches('0');
+                let nb = nb.trim_start_matches('0');
+                let ord = na.len().cmp(&nb.len()).then_with(|| na.cmp(nb));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
         }
     }
 }
 
+/// Letters of a 0 based xlsx column: 0 is "A", 26 is "AA".
+pub fn column_name(column: usize) -> String {
+    let mut name = Vec::new();
+    let mut n = column + 1;
+    while n > 0 {
+        n -= 1;
+        name.push(b'A' + (n % 26) as u8);
+        n /= 26;
+    }
+    name.iter().rev().map(|&b| b as char).collect()
+}
+
+/// 0 based column of a cell reference, "BOM!$C$1" or "C1" is 2.
+pub fn reference_column(reference: &str) -> Option<usize> {
+    let cell = reference.rsplit('!').next()?.trim_start_matches(['=', '$']);
+    let letters: String = cell.chars().take_while(char::is_ascii_alphabetic).collect();
+    if letters.is_empty() {
+        return None;
+    }
+    let n = letters
+        .to_ascii_uppercase()
+        .bytes()
+        .fold(0, |n, b| n * 26 + (b - b'A') as usize + 1);
+    Some(n - 1)
+}
+
+/// All the rows of a csv file or of the first sheet of a workbook, as text.
+pub fn read_sheet_rows<P: AsRef<Path>>(path: P) -> Result<Vec<Vec<String>>> {
+    let path = path.as_ref();
+    match BomFormat::from_path(path) {
+        Some(BomFormat::Csv) => {
+            let mut rd = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_path(path)?;
+            rd.records()
+                .map(|r| Ok(r?.iter().map(str::to_string).collect()))
+                .collect()
+        }
+        Some(_) => {
+            let mut workbook = open_workbook_auto(path)?;
+            let sheet = match workbook.sheet_names().first() {
+                Some(s) => s.clone(),
+                None => bail!("No sheet found in file"),
+            };
+            match workbook.worksheet_range(&sheet) {
+                Some(Ok(range)) => Ok(range
+                    .rows()
+                    .map(|r| {
+                        r.iter()
+                            .map(|c| match c {
+                                DataType::String(s) => s.to_string(),
+                                DataType::Int(i) => i.to_string(),
+                                DataType::Float(f) => f.to_string(),
+                                _ => String::new(),
+                            })
+                            .collect()
+                    })
+                    .collect()),
+                _ => bail!("Unable to read sheet {}", sheet),
+            }
+        }
+        None => bail!("Unknown file format: {}", path.display()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -238,6 +378,60 @@ mod tests {
         }
         //assert_eq!(0, 1);
     }
+    #[test]
+    fn test_is_dnp_marker() {
+        let data = [
+            ("NP", true),
+            ("NP (0R)", true),
+            ("NP Connector", true),
+            ("DNP", true),
+            ("dnp 100nF", true),
+            ("DNF", true),
+            ("Not Fitted", true),
+            ("NOT POPULATED", true),
+            ("Do not fit", true),
+            ("NPN", false),
+            ("100nF", false),
+            ("Connector", false),
+            ("", false),
+        ];
+
+        for i in data.iter() {
+            assert_eq!(is_dnp_marker(i.0), i.1, "{}", i.0);
+        }
+    }
+
+    #[test]
+    fn test_comment_to_number() {
+        let data = [
+            ("1k", Some(1e3)),
+            ("10k", Some(1e4)),
+            ("2k2", Some(2200.0)),
+            ("100nF", Some(100e-9)),
+            ("4R7", Some(4.7)),
+            ("NP", None),
+            ("uno", None),
+            ("Connector", None),
+        ];
+
+        for i in data.iter() {
+            match (comment_to_number(i.0), i.1) {
+                (Some(a), Some(b)) => assert!((a - b).abs() < b * 1e-6, "{}", i.0),
+                (a, b) => assert_eq!(a, b, "{}", i.0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut data = vec!["R10", "R2", "C1", "R1", "R02", "J20", "J3", "U1A", "U1"];
+        data.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            data,
+            ["C1", "J3", "J20", "R1", "R2", "R02", "R10", "U1", "U1A"]
+        );
+    }
+
     #[test]
     fn test_detect_measure_unit() {
         let test_data = [
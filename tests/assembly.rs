@@ -0,0 +1,71 @@
+use calamine::{Reader, Xlsx};
+use mergebom_web::assembly::{AssemblySplit, AssemblyStep};
+use mergebom_web::bom::{Bom, Item, Layer, MountTechnology, SortBy};
+use mergebom_web::outjob::{OutJobMeta, OutJobXlsx};
+use std::io::Cursor;
+use std::str::FromStr;
+
+fn splits() -> Vec<AssemblySplit> {
+    Bom::loader(
+        &["tests/data/assembly0.csv"],
+        &["comment", "footprint"].map(String::from),
+    )
+    .assembly_splits(SortBy::Designator)
+}
+
+#[test]
+fn step_of_item() {
+    let smd = Item::new().with_mount_technology(MountTechnology::Smd);
+    assert_eq!(AssemblyStep::of(&smd), AssemblyStep::TopSmd);
+    let bottom = smd.clone().with_layer(Layer::Bottom);
+    assert_eq!(AssemblyStep::of(&bottom), AssemblyStep::BottomSmd);
+    let tht = Item::new()
+        .with_mount_technology(MountTechnology::Tht)
+        .with_layer(Layer::Bottom);
+    assert_eq!(AssemblyStep::of(&tht), AssemblyStep::Tht);
+    assert_eq!(AssemblyStep::of(&Item::new()), AssemblyStep::Manual);
+
+    assert_eq!(AssemblyStep::TopSmd.to_string(), "top_smd");
+    assert_eq!(AssemblyStep::from_str("tht").unwrap(), AssemblyStep::Tht);
+    assert_eq!(AssemblyStep::BottomSmd.label(), "Bottom SMD");
+}
+
+#[test]
+fn split_quantities() {
+    let splits = splits();
+    let steps: Vec<AssemblyStep> = splits.iter().map(|s| s.step).collect();
+    assert_eq!(
+        steps,
+        [
+            AssemblyStep::TopSmd,
+            AssemblyStep::BottomSmd,
+            AssemblyStep::Tht,
+            AssemblyStep::Manual
+        ]
+    );
+    let counts: Vec<(usize, usize)> = splits.iter().map(|s| (s.lines(), s.parts())).collect();
+    assert_eq!(counts, [(2, 3), (1, 1), (1, 1), (1, 2)]);
+
+    // The same part on both sides is a line per side
+    let bottom = &splits[1].table;
+    assert_eq!(bottom.rows[0].fields[0], "1");
+    assert_eq!(bottom.rows[0].fields[1], "R3");
+    assert_eq!(bottom.dnp.len(), 1);
+
+    let mut job = OutJobXlsx::in_memory().unwrap();
+    job.write_splits(&splits, &OutJobMeta::default()).unwrap();
+    let mut wk = Xlsx::new(Cursor::new(job.into_bytes().unwrap())).unwrap();
+    assert_eq!(
+        wk.sheet_names(),
+        [
+            "Steps",
+            "Top SMD",
+            "Bottom SMD",
+            "Bottom SMD DNP",
+            "THT",
+            "Manual"
+        ]
+    );
+    let dnp = wk.worksheet_range("Bottom SMD DNP").unwrap().unwrap();
+    assert_eq!(dnp.height(), bottom.dnp.len() + 2);
+}
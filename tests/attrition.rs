@@ -0,0 +1,71 @@
+use mergebom_web::attrition::{AttritionRule, AttritionRules, GROSS_QUANTITY, NET_QUANTITY};
+use mergebom_web::bom::Bom;
+use mergebom_web::cost::PriceList;
+
+fn rules() -> AttritionRules {
+    AttritionRules::new(vec![
+        AttritionRule {
+            footprint: "0603".to_string(),
+            percent: 10.0,
+            min_extra: 20,
+            ..Default::default()
+        },
+        AttritionRule {
+            category: "C".to_string(),
+            percent: 5.0,
+            ..Default::default()
+        },
+    ])
+}
+
+#[test]
+fn spare_parts() {
+    let r = rules();
+    // Minimum extra, then the percentage once above it
+    assert_eq!(r.gross(10, "** R Resistors **", "0603_[1608]"), 30);
+    assert_eq!(r.gross(300, "** R Resistors **", "0603_[1608]"), 330);
+    assert_eq!(r.gross(0, "** R Resistors **", "0603_[1608]"), 0);
+    // First matching rule, by category letter or name
+    assert_eq!(r.gross(100, "** C Capacitors **", "0603_[1608]"), 120);
+    assert_eq!(r.gross(100, "** C Capacitors **", "0805"), 105);
+    assert_eq!(r.gross(10, "** C Capacitors **", "0805"), 11);
+    assert_eq!(r.gross(100, "** U IC **", "LQFP64"), 100);
+
+    let by_name = AttritionRule {
+        category: "capacitors".to_string(),
+        min_extra: 2,
+        ..Default::default()
+    };
+    assert_eq!(by_name.extra(1), 2);
+}
+
+#[test]
+fn net_and_gross_columns() {
+    let bom = Bom::loader(
+        &["tests/data/test13.csv"],
+        &["comment", "footprint"].map(String::from),
+    )
+    .merge();
+    let mut data = bom.variants_table(Default::default()).unwrap();
+    data.add_attrition(&rules(), 5);
+
+    let n = data.headers.len();
+    assert_eq!(data.headers[n - 2], NET_QUANTITY);
+    assert_eq!(data.headers[n - 1], GROSS_QUANTITY);
+    let row = |d: &str| {
+        data.rows
+            .iter()
+            .find(|r| r.fields.iter().any(|f| f == d))
+            .unwrap()
+    };
+    assert_eq!(row("R1").fields[n - 2..], ["5", "25"]);
+    // C1 has no footprint, the capacitor rule applies
+    assert_eq!(row("C1").fields[n - 2..], ["5", "6"]);
+    assert_eq!(row("U1").fields[n - 2..], ["5", "5"]);
+
+    // The spare parts are priced too
+    let mut prices = PriceList::new("EUR");
+    prices.add("GRM188R71H104KA93D", 1, 0.5);
+    data.add_costs(&prices, 5);
+    assert_eq!(data.cost.unwrap().total, 3.0);
+}
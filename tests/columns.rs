@@ -0,0 +1,186 @@
+use calamine::{DataType, Reader, Xlsx};
+use mergebom_web::bom::{Bom, BomFormat, ItemsTable};
+use mergebom_web::columns::{Column, ColumnSpec, Computed};
+use mergebom_web::cost::PriceList;
+use mergebom_web::outjob::{OutFormat, OutJobMeta, XlsxOptions, XlsxSheets};
+use mergebom_web::style::XlsxStyle;
+use std::io::Cursor;
+
+const TEST_DIR: &str = "tests/data";
+
+fn table() -> ItemsTable {
+    let prices = PriceList::load(format!("{}/prices0.csv", TEST_DIR)).unwrap();
+    let t = format!("{}/test12.csv", TEST_DIR);
+    let bom = Bom::loader(&[t], &["comment", "footprint"].map(String::from));
+    let mut data = bom.merge().odered_vector_table();
+    data.add_costs(&prices, 10);
+    data
+}
+
+#[test]
+fn order_and_labels() {
+    let mut data = table();
+    let before = data.clone();
+    data.apply_columns(&ColumnSpec::default());
+    assert_eq!(data, before);
+
+    data.apply_columns(&ColumnSpec::new(vec![
+        Column::field("quantity"),
+        Column::field("Comment").with_label("Value"),
+        Column::field("DESIGNATOR"),
+        Column::field("No such field"),
+    ]));
+    assert_eq!(
+        data.headers,
+        ["Quantity", "Value", "Designator", "No such field"]
+    );
+    let r1 = data
+        .rows
+        .iter()
+        .find(|r| r.fields[2].starts_with("R1"))
+        .unwrap();
+    assert_eq!(r1.fields[1], "10k");
+    assert_eq!(r1.fields[3], "");
+    assert_eq!(data.rows.len(), before.rows.len());
+    assert!(data
+        .rows
+        .iter()
+        .chain(data.dnp.iter())
+        .all(|r| r.fields.len() == 4));
+    assert_eq!(data.cost, before.cost);
+}
+
+#[test]
+fn computed_columns() {
+    let mut data = table();
+    data.apply_columns(&ColumnSpec::new(vec![
+        Column::field("Designator"),
+        Column::computed(Computed::DesignatorCount),
+        Column::computed(Computed::UnitPrice),
+        Column::computed(Computed::ExtendedPrice).with_label("Total"),
+        Column::computed(Computed::Sources),
+    ]));
+    assert_eq!(
+        data.headers,
+        [
+            "Designator",
+            "Designator count",
+            "Unit price (EUR)",
+            "Total",
+            "Sources"
+        ]
+    );
+    let r1 = data
+        .rows
+        .iter()
+        .find(|r| r.fields[0].starts_with("R1"))
+        .unwrap();
+    assert_eq!(r1.fields[1], "3");
+    assert_eq!(r1.fields[2], "0.0500");
+    assert_eq!(r1.fields[3], "1.50");
+    assert_eq!(r1.fields[4], "test12.csv");
+    assert_eq!(data.dnp[0].fields[1], "1");
+    assert_eq!(data.dnp[0].fields[2], "");
+}
+
+#[test]
+fn load_spec() {
+    let spec = ColumnSpec::load(format!("{}/columns0.toml", TEST_DIR)).unwrap();
+    assert_eq!(spec.columns.len(), 5);
+    assert_eq!(spec.columns[1].label, "Refs");
+    assert_eq!(spec.columns[2].computed, Some(Computed::DesignatorCount));
+
+    let mut data = table();
+    data.apply_columns(&spec);
+    assert_eq!(
+        data.headers,
+        [
+            "Quantity",
+            "Refs",
+            "Designator count",
+            "Value",
+            "Unit price (EUR)"
+        ]
+    );
+    assert!(ColumnSpec::load(format!("{}/test12.csv", TEST_DIR)).is_err());
+}
+
+#[test]
+fn reordered_xlsx() {
+    let mut data = table();
+    data.apply_columns(&ColumnSpec::new(vec![
+        Column::field("Designator"),
+        Column::field("Comment"),
+        Column::field("Quantity").with_label("Pcs"),
+    ]));
+    // Found by where it came from, not by its label
+    assert_eq!(data.quantity_column(), Some(2));
+    let parts = data.parts();
+    assert!(parts > 0);
+
+    let xlsx = XlsxOptions {
+        sheets: XlsxSheets {
+            cover: true,
+            ..Default::default()
+        },
+        style: XlsxStyle {
+            quantity_format: "0".to_string(),
+            category_rows: false,
+            subtotals: true,
+            ..Default::default()
+        },
+    };
+    let bytes = OutFormat::Xlsx
+        .to_bytes(&data, &OutJobMeta::default(), &xlsx)
+        .unwrap();
+    let mut wk = Xlsx::new(Cursor::new(bytes.clone())).unwrap();
+    let cover = wk.worksheet_range("Cover").unwrap().unwrap();
+    let resistors = (0..cover.height())
+        .find(|r| {
+            cover
+                .get((*r, 0))
+                .and_then(|d| d.get_string())
+                .is_some_and(|c| c.contains("Resistors"))
+        })
+        .unwrap();
+    assert_eq!(cover.get((resistors, 2)), Some(&DataType::Float(3.0)));
+    let bom = wk.worksheet_range("BOM").unwrap().unwrap();
+    // Quantities are numbers in their column, the designators text
+    assert_eq!(
+        bom.get((1, 0)),
+        Some(&DataType::String("R1, R2, R3".into()))
+    );
+    assert_eq!(bom.get((1, 2)), Some(&DataType::Float(3.0)));
+    let subtotal = data.rows.len() + 1;
+    assert_eq!(
+        bom.get((subtotal, 0)),
+        Some(&DataType::String(format!("{} lines", data.rows.len())))
+    );
+    assert_eq!(bom.get((subtotal, 2)), Some(&DataType::Float(parts as f64)));
+
+    let html = OutFormat::Report
+        .to_bytes(&data, &OutJobMeta::default(), &xlsx)
+        .unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert!(html.contains("<td class=\"quantity\">3</td>"));
+
+    // Read back with its quantity
+    let bom = Bom::from_reader(
+        Cursor::new(bytes),
+        BomFormat::Xlsx,
+        &["comment".to_string()],
+    )
+    .unwrap();
+    let r = bom.items().find(|i| i.comment() == Some("10k")).unwrap();
+    assert_eq!(r.quantity(), 3);
+    assert_eq!(bom.items().map(|i| i.quantity()).sum::<usize>(), parts);
+
+    // Without the quantity there is none to find
+    data.apply_columns(&ColumnSpec::new(vec![
+        Column::field("Pcs").with_label("Qty")
+    ]));
+    assert_eq!(data.quantity_column(), Some(0));
+    data.apply_columns(&ColumnSpec::new(vec![Column::field("Designator")]));
+    assert_eq!(data.quantity_column(), None);
+    assert_eq!(data.parts(), 0);
+}
@@ -0,0 +1,120 @@
+use mergebom_web::bom::Bom;
+use mergebom_web::cost::PriceList;
+
+const TEST_DIR: &str = "tests/data";
+
+#[test]
+fn price_breaks() {
+    let prices = PriceList::load(format!("{}/prices0.csv", TEST_DIR)).unwrap();
+    assert_eq!(prices.currency(), "EUR");
+    assert_eq!(prices.len(), 3);
+
+    assert_eq!(prices.unit_price("9238603", 1), Some(0.10));
+    assert_eq!(prices.unit_price("9238603", 9), Some(0.10));
+    assert_eq!(prices.unit_price("9238603", 10), Some(0.05));
+    assert_eq!(prices.unit_price("9238603", 1000), Some(0.01));
+    assert_eq!(prices.unit_price("81-grm188r71h104ka3d", 5), Some(0.02));
+    assert_eq!(prices.unit_price("2432084", 1), None);
+
+    let mut list = PriceList::new("usd");
+    list.add("ABC", 10, 2.0);
+    assert_eq!(list.currency(), "USD");
+    assert_eq!(list.unit_price("abc", 1), Some(2.0));
+
+    let rows = vec![
+        vec![
+            "MPN".to_string(),
+            "Price".to_string(),
+            "Currency".to_string(),
+        ],
+        vec!["A".to_string(), "1".to_string(), "EUR".to_string()],
+        vec!["B".to_string(), "1".to_string(), "USD".to_string()],
+    ];
+    assert!(PriceList::from_rows(&rows).is_err());
+    assert!(PriceList::from_rows(&rows[1..]).is_err());
+}
+
+#[test]
+fn price_formats() {
+    let cases = [
+        ("A", "0.12", 0.12),
+        ("B", "0,12", 0.12),
+        ("C", "€ 1,234.50", 1234.5),
+        ("D", "1.234,50 €", 1234.5),
+        ("E", "1,234", 1234.0),
+        ("F", "1.234.567,8", 1234567.8),
+        ("G", "$1,234,567.25", 1234567.25),
+        ("H", "0,125", 0.125),
+    ];
+    let mut rows = vec![vec!["MPN".to_string(), "Price".to_string()]];
+    for (key, price, _) in cases.iter() {
+        rows.push(vec![key.to_string(), price.to_string()]);
+    }
+    let list = PriceList::from_rows(&rows).unwrap();
+    for (key, price, value) in cases.iter() {
+        let p = list.unit_price(key, 1).unwrap();
+        assert!((p - value).abs() < 1e-9, "{} is {}", price, p);
+    }
+}
+
+#[test]
+fn costed_table() {
+    let prices = PriceList::load(format!("{}/prices0.csv", TEST_DIR)).unwrap();
+    let t = format!("{}/test12.csv", TEST_DIR);
+    let bom = Bom::loader(&[t], &["comment", "footprint"].map(String::from));
+    let mut data = bom.merge().odered_vector_table();
+    data.add_costs(&prices, 10);
+
+    let n = data.headers.len();
+    assert_eq!(
+        data.headers[n - 2..],
+        ["Unit price (EUR)", "Extended price (EUR)"]
+    );
+    let price = |d: &str| {
+        let row = data
+            .rows
+            .iter()
+            .find(|r| r.fields[1].starts_with(d))
+            .unwrap();
+        (row.fields[n - 2].clone(), row.fields[n - 1].clone())
+    };
+    // 3 per board, 30 at the 10 pieces break
+    assert_eq!(price("R1"), ("0.0500".to_string(), "1.50".to_string()));
+    // Farnell codes conflict, the Mouser one has a price
+    assert_eq!(price("C1"), ("0.0200".to_string(), "0.40".to_string()));
+    assert_eq!(price("U1"), (String::new(), String::new()));
+
+    let cost = data.cost.unwrap();
+    assert_eq!(cost.build_quantity, 10);
+    assert!((cost.total - 1.90).abs() < 1e-9);
+    assert_eq!(cost.categories.len(), 2);
+    assert_eq!(cost.unpriced.len(), 2);
+    assert!(data.dnp.iter().all(|r| r.fields.len() == n));
+}
+
+#[test]
+fn unsorted_category_totals() {
+    let prices = PriceList::load(format!("{}/prices0.csv", TEST_DIR)).unwrap();
+    let t = format!("{}/test12.csv", TEST_DIR);
+    let bom = Bom::loader(&[t], &["comment", "footprint"].map(String::from));
+    let mut data = bom.merge().odered_vector_table();
+    // A capacitor line after the other categories
+    let c = data
+        .rows
+        .iter()
+        .position(|r| r.fields[1].starts_with('C'))
+        .unwrap();
+    let c = data.rows[c].clone();
+    data.rows.push(c);
+    data.add_costs(&prices, 10);
+
+    let cost = data.cost.unwrap();
+    assert_eq!(cost.categories.len(), 2);
+    let capacitors = cost
+        .categories
+        .iter()
+        .find(|c| c.category.contains("Capacitors"))
+        .unwrap();
+    assert!((capacitors.total - 0.80).abs() < 1e-9);
+    assert!((cost.total - 2.30).abs() < 1e-9);
+}
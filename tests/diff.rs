@@ -0,0 +1,38 @@
+use mergebom_web::bom::Bom;
+
+const TEST_DIR: &str = "tests/data";
+
+#[test]
+fn diff_revisions() {
+    let keys = ["comment", "footprint"].map(String::from);
+    let old = Bom::loader(&[format!("{}/test0.csv", TEST_DIR)], &keys);
+    let new = Bom::loader(&[format!("{}/test9.csv", TEST_DIR)], &keys);
+
+    let diff = old.diff(&new);
+    println!("{}", diff);
+
+    let added: Vec<_> = diff.added.iter().map(|r| r.unique_id.as_str()).collect();
+    assert_eq!(
+        added,
+        ["** C Capacitors **-1uF-0603_[1608]", "** U IC **-LM358-SO8"]
+    );
+    assert!(diff.removed.is_empty());
+
+    let res = diff
+        .changed
+        .iter()
+        .find(|c| c.item.unique_id == "** R Resistors **-1k-0603_[1608]")
+        .unwrap();
+    assert_eq!(res.old_quantity, "2");
+    assert_eq!(res.new_quantity(), "3");
+    assert!(res
+        .fields
+        .iter()
+        .any(|f| f.header == "Description" && f.old == "Resistor" && f.new == "Resistor 1%"));
+
+    assert_eq!(diff.moved.len(), 1);
+    assert_eq!(diff.moved[0].designator, "C1");
+    assert_eq!(diff.moved[0].to, "** C Capacitors **-1uF-0603_[1608]");
+
+    assert!(old.diff(&old).is_empty());
+}
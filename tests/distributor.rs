@@ -0,0 +1,98 @@
+use mergebom_web::bom::Bom;
+use mergebom_web::distributor::{Distributor, OrderCodeIssueKind};
+
+const TEST_DIR: &str = "tests/data";
+
+fn load() -> Bom {
+    let t = format!("{}/test12.csv", TEST_DIR);
+    Bom::loader(&[t], &["comment", "footprint"].map(String::from)).merge()
+}
+
+#[test]
+fn distributor_header() {
+    assert_eq!(
+        Distributor::from_header("CODE Farnell"),
+        Some(Distributor::Farnell)
+    );
+    assert_eq!(
+        Distributor::from_header("code digi-key"),
+        Some(Distributor::Digikey)
+    );
+    assert_eq!(Distributor::from_header("CODE due"), None);
+    assert_eq!(Distributor::from_header("NOTE mouser"), None);
+    assert_eq!(
+        "mouser".parse::<Distributor>().unwrap(),
+        Distributor::Mouser
+    );
+}
+
+#[test]
+fn order_codes() {
+    let bom = load();
+    let find = |d: &str| {
+        bom.items()
+            .find(|i| i.designators().first().map(|s| s.as_str()) == Some(d))
+            .unwrap()
+    };
+
+    // Normalized and de-duplicated over the merged rows
+    let r = find("R1");
+    assert_eq!(r.order_codes(Distributor::Mouser), ["603-RC0603FR-0710KL"]);
+    assert_eq!(r.order_codes(Distributor::Farnell), ["9238603"]);
+    assert_eq!(r.order_codes(Distributor::Digikey), ["311-10.0KHRCT-ND"]);
+    assert_eq!(find("U1").order_codes(Distributor::Farnell), ["2432084"]);
+
+    let issues: Vec<(String, Distributor, OrderCodeIssueKind)> = bom
+        .order_code_issues()
+        .into_iter()
+        .map(|i| (i.designators.join(" "), i.distributor, i.kind))
+        .collect();
+    assert_eq!(
+        issues,
+        [
+            (
+                "C1 C2".to_string(),
+                Distributor::Farnell,
+                OrderCodeIssueKind::Conflict
+            ),
+            (
+                "C1 C2".to_string(),
+                Distributor::Digikey,
+                OrderCodeIssueKind::Invalid
+            ),
+            (
+                "C1 C2".to_string(),
+                Distributor::Digikey,
+                OrderCodeIssueKind::Conflict
+            ),
+            (
+                "U1".to_string(),
+                Distributor::Mouser,
+                OrderCodeIssueKind::Invalid
+            ),
+        ]
+    );
+}
+
+#[test]
+fn cart() {
+    let bom = load();
+
+    let mut csv = Vec::new();
+    assert_eq!(bom.write_cart(Distributor::Mouser, &mut csv).unwrap(), 2);
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "Mouser Part Number,Quantity,Customer Part Number\n\
+         81-GRM188R71H104KA3D,2,C1 C2\n\
+         603-RC0603FR-0710KL,3,R1 R2 R3\n"
+    );
+
+    let mut csv = Vec::new();
+    assert_eq!(bom.write_cart(Distributor::Farnell, &mut csv).unwrap(), 2);
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "Order Code,Quantity,Line Note\n\
+         9238603,3,R1 R2 R3\n\
+         2432084,1,U1\n"
+    );
+}
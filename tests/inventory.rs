@@ -0,0 +1,83 @@
+use mergebom_web::attrition::{AttritionRule, AttritionRules};
+use mergebom_web::bom::Bom;
+use mergebom_web::inventory::Inventory;
+
+const TEST_DIR: &str = "tests/data";
+
+#[test]
+fn shortage_report() {
+    let inv = Inventory::load(format!("{}/stock0.csv", TEST_DIR)).unwrap();
+    // The same code on two shelves is one entry
+    assert_eq!(inv.entries().len(), 3);
+    assert_eq!(inv.entries()[1].quantity, 3);
+    assert_eq!(inv.entries()[1].location, "B3, B4");
+
+    // A saved inventory matches like the loaded one
+    let json = serde_json::to_string(&inv).unwrap();
+    let saved: Inventory = serde_json::from_str(&json).unwrap();
+    assert_eq!(saved, inv);
+
+    let t = format!("{}/test13.csv", TEST_DIR);
+    let bom = Bom::loader(&[t], &["comment", "footprint"].map(String::from)).merge();
+    let report = bom.shortage_report(&inv, 5, &AttritionRules::default());
+    assert_eq!(report.build_quantity, 5);
+    let line = |d: &str| {
+        report
+            .lines
+            .iter()
+            .find(|l| l.designators.iter().any(|x| x == d))
+            .unwrap()
+    };
+
+    // By value and footprint, R1 and R2 share the 15 resistors in order
+    let r1 = line("R1");
+    assert_eq!(r1.code, "ART-0001");
+    assert_eq!((r1.required, r1.available, r1.missing), (5, 15, 0));
+    let r2 = line("R2");
+    assert_eq!((r2.required, r2.available, r2.missing), (5, 10, 0));
+
+    // By MPN
+    let c1 = line("C1");
+    assert_eq!(c1.location, "A2");
+    assert_eq!(c1.missing, 0);
+
+    // By internal code, not enough on hand
+    let u1 = line("U1");
+    assert_eq!((u1.required, u1.available, u1.missing), (5, 3, 2));
+
+    // Not in the warehouse
+    let l1 = line("L1");
+    assert_eq!(l1.code, "");
+    assert_eq!(l1.missing, 5);
+
+    let short: Vec<_> = report
+        .shortages()
+        .map(|l| l.designators.join(", "))
+        .collect();
+    assert_eq!(short, ["L1", "U1"]);
+
+    // Spare resistors are reserved too
+    let rules = AttritionRules::new(vec![AttritionRule {
+        category: "R".to_string(),
+        percent: 10.0,
+        min_extra: 3,
+        ..Default::default()
+    }]);
+    let report = bom.shortage_report(&inv, 5, &rules);
+    let line = |d: &str| {
+        report
+            .lines
+            .iter()
+            .find(|l| l.designators.iter().any(|x| x == d))
+            .unwrap()
+    };
+    let r2 = line("R2");
+    assert_eq!((r2.required, r2.available, r2.missing), (8, 7, 1));
+    assert_eq!(line("U1").required, 5);
+}
+
+#[test]
+fn missing_header() {
+    let rows = vec![vec!["Location".to_string(), "Quantity".to_string()]];
+    assert!(Inventory::from_rows(&rows).is_err());
+}
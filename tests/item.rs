@@ -0,0 +1,50 @@
+use mergebom_web::bom::{Category, Field, Item, Layer, MountTechnology};
+
+#[test]
+fn build_item() {
+    let item = Item::new()
+        .with_designators(["R1", "R2"])
+        .with_comment("10k")
+        .with_footprint("0603_[1608]")
+        .with_description("Resistor")
+        .with_layer(Layer::Bottom)
+        .with_mount_technology(MountTechnology::Smd)
+        .with_manufacturer("Yageo")
+        .with_mpn("RC0603FR-0710KL")
+        .with_field("CODE farnell", "9238603")
+        .with_field("Assembly note", "glue");
+
+    assert_eq!(item.designators(), ["R1", "R2"]);
+    assert_eq!(item.quantity(), 2);
+    assert_eq!(item.category, Category::Resistors);
+    assert_eq!(item.comment(), Some("10k"));
+    assert_eq!(item.footprint(), Some("0603_[1608]"));
+    assert_eq!(item.description(), Some("Resistor"));
+    assert_eq!(item.layer(), Some(Layer::Bottom));
+    assert_eq!(item.mount_technology(), Some(MountTechnology::Smd));
+    assert_eq!(item.manufacturer(), Some("Yageo"));
+    assert_eq!(item.mpn(), Some("RC0603FR-0710KL"));
+    assert_eq!(
+        item.field("CODE farnell"),
+        Some(&Field::List(vec!["9238603".to_string()]))
+    );
+
+    let mut extra: Vec<_> = item.extra_fields().map(|(k, _)| k.as_str()).collect();
+    extra.sort();
+    assert_eq!(extra, ["assembly note", "code farnell"]);
+}
+
+#[test]
+fn parse_layer_and_mount() {
+    assert_eq!("TopLayer".parse::<Layer>().unwrap(), Layer::Top);
+    assert_eq!("B.Cu".parse::<Layer>().unwrap(), Layer::Bottom);
+    assert!("inner".parse::<Layer>().is_err());
+    assert_eq!(
+        "Through Hole".parse::<MountTechnology>().unwrap(),
+        MountTechnology::Tht
+    );
+    assert_eq!(
+        "SMD".parse::<MountTechnology>().unwrap(),
+        MountTechnology::Smd
+    );
+}
@@ -0,0 +1,58 @@
+use mergebom_web::bom::{Bom, Field, Severity};
+use mergebom_web::library::PartsLibrary;
+
+const TEST_DIR: &str = "tests/data";
+
+#[test]
+fn enrich() {
+    let lib = PartsLibrary::load(format!("{}/library0.json", TEST_DIR)).unwrap();
+    assert_eq!(lib.parts().len(), 3);
+
+    let t = format!("{}/test13.csv", TEST_DIR);
+    let mut bom = Bom::loader(&[t], &["comment", "footprint"].map(String::from));
+    bom.enrich(&lib);
+    let merged = bom.merge();
+    let find = |d: &str| {
+        merged
+            .items()
+            .find(|i| i.designators().iter().any(|x| x == d))
+            .unwrap()
+    };
+
+    // Value and footprint: 10k and 10000 are the same part, blank fields filled
+    let r = find("R1");
+    assert_eq!(find("R2").mpn(), Some("RC0603FR-0710KL"));
+    assert_eq!(find("R2").description(), Some("Resistor"));
+    assert_eq!(r.mpn(), Some("RC0603FR-0710KL"));
+    assert_eq!(r.manufacturer(), Some("Yageo"));
+    assert_eq!(
+        r.field("CODE internal"),
+        Some(&Field::List(vec!["ART-0001".to_string()]))
+    );
+    assert_eq!(
+        r.field("CODE farnell"),
+        Some(&Field::List(vec!["9238603".to_string()]))
+    );
+
+    // By MPN, the footprint is a merge key and the id follows it
+    let c = find("C1");
+    assert_eq!(c.footprint(), Some("0603_[1608]"));
+    assert_eq!(c.description(), Some("Ceramic X7R 50V"));
+    assert_eq!(c.unique_id(), "** C Capacitors **-100nF-0603_[1608]");
+
+    // By internal code
+    let u = find("U1");
+    assert_eq!(u.mpn(), Some("STM32F401RET6"));
+    assert_eq!(u.footprint(), Some("LQFP64"));
+
+    // Same value and footprint, but an inductor is not the capacitor
+    let l = find("L1");
+    assert_eq!(
+        l.field("CODE internal"),
+        Some(&Field::List(vec![String::new()]))
+    );
+    let diags = lib.unmatched(&merged);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].severity, Severity::Warning);
+    assert_eq!(diags[0].message, "L1: no library match");
+}
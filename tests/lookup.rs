@@ -0,0 +1,217 @@
+use mergebom_web::bom::{Bom, Severity};
+use mergebom_web::lookup::{
+    DiskCache, HttpBackend, Lifecycle, LookupBackend, MemoryBackend, PartInfo, PartLookup,
+};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const TEST_DIR: &str = "tests/data";
+
+fn part(mpn: &str, stock: u64, lifecycle: Lifecycle) -> PartInfo {
+    PartInfo {
+        mpn: mpn.to_string(),
+        stock: Some(stock),
+        price: Some(0.1),
+        currency: "EUR".to_string(),
+        lifecycle,
+        ..Default::default()
+    }
+}
+
+/// Serve `GET /api/parts/<mpn>` from `parts` on localhost, count the requests.
+fn mock_server(parts: MemoryBackend) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/api", listener.local_addr().unwrap());
+    let count = Arc::new(AtomicUsize::new(0));
+    let requests = count.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut line = String::new();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            reader.read_line(&mut line).unwrap();
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+            }
+            requests.fetch_add(1, Ordering::SeqCst);
+
+            let path = line.split_whitespace().nth(1).unwrap_or_default();
+            let mpn = path.trim_start_matches("/api/parts/").replace("%2F", "/");
+            let mut response = match parts.fetch(&mpn).unwrap() {
+                Some(p) => {
+                    let body = serde_json::to_string(&p).unwrap();
+                    format!(
+                        "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .into_bytes()
+                }
+                None if mpn == "OLD" => {
+                    b"HTTP/1.0 301 Moved\r\nLocation: /api/parts/LM358D%2FR\r\n\r\n".to_vec()
+                }
+                None => b"HTTP/1.0 404 Not Found\r\n\r\n".to_vec(),
+            };
+            // Bytes past the Content-Length are not part of the body
+            response.extend_from_slice(b"\xff\xfe");
+            stream.write_all(&response).unwrap();
+        }
+    });
+    (url, count)
+}
+
+/// Server recording the api key of each request. "HOME" redirects on the
+/// same host, "AWAY" to `away`, "HUGE" answers 2 MB, the rest is unknown.
+fn key_server(away: Option<String>) -> (String, Arc<Mutex<Vec<Option<String>>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/api", listener.local_addr().unwrap());
+    let keys = Arc::new(Mutex::new(Vec::new()));
+    let seen = keys.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut line = String::new();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            reader.read_line(&mut line).unwrap();
+            let mut key = None;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("x-api-key") {
+                        key = Some(value.trim().to_string());
+                    }
+                }
+            }
+            seen.lock().unwrap().push(key);
+
+            let path = line.split_whitespace().nth(1).unwrap_or_default();
+            let response = match path.trim_start_matches("/api/parts/") {
+                "HOME" => "HTTP/1.0 302 Found\r\nLocation: /api/parts/NOPE\r\n\r\n".to_string(),
+                "AWAY" => format!(
+                    "HTTP/1.0 302 Found\r\nLocation: {}\r\n\r\n",
+                    away.as_deref().unwrap_or_default()
+                ),
+                "HUGE" => format!(
+                    "HTTP/1.0 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    2 << 20,
+                    " ".repeat(2 << 20)
+                ),
+                _ => "HTTP/1.0 404 Not Found\r\n\r\n".to_string(),
+            };
+            // The client stops reading a too big answer
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    (url, keys)
+}
+
+#[test]
+fn annotate_table() {
+    let mut parts = MemoryBackend::new();
+    parts.add(part("RC0603FR-0710KL", 10, Lifecycle::Active));
+    parts.add(part("GRM188R71H104KA93D", 0, Lifecycle::Obsolete));
+
+    let t = format!("{}/test11.csv", TEST_DIR);
+    let bom = Bom::loader(&[t], &["comment", "footprint"].map(String::from));
+    let mut data = bom.merge().odered_vector_table();
+    PartLookup::new(parts).annotate(&mut data, 5);
+
+    let n = data.headers.len();
+    assert_eq!(
+        data.headers[n - 3..],
+        ["Stock", "Lifecycle", "Distributor price"]
+    );
+    let r = data
+        .rows
+        .iter()
+        .find(|r| r.fields[1].starts_with("R1"))
+        .unwrap();
+    assert_eq!(r.fields[n - 3..], ["10", "active", "0.1000 EUR"]);
+
+    let mut diags: Vec<(Severity, String)> = data
+        .diagnostics
+        .iter()
+        .map(|d| (d.severity, d.message.clone()))
+        .collect();
+    diags.sort();
+    assert_eq!(
+        diags,
+        [
+            (
+                Severity::Warning,
+                "RC0603FR-0710KL: 10 in stock, 20 needed".to_string()
+            ),
+            (Severity::Error, "GRM188R71H104KA93D: obsolete".to_string()),
+            (
+                Severity::Error,
+                "GRM188R71H104KA93D: out of stock".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn http_backend_and_cache() {
+    let mut parts = MemoryBackend::new();
+    parts.add(part("LM358D/R", 100, Lifecycle::Nrnd));
+    let (url, count) = mock_server(parts);
+
+    let backend = HttpBackend::new(&url)
+        .unwrap()
+        .with_timeout(Duration::from_secs(2));
+    assert_eq!(
+        backend.fetch("LM358D/R").unwrap().unwrap().lifecycle,
+        Lifecycle::Nrnd
+    );
+    assert_eq!(backend.fetch("NOPE").unwrap(), None);
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+    assert_eq!(backend.fetch("OLD").unwrap().unwrap().mpn, "LM358D/R");
+    assert_eq!(count.load(Ordering::SeqCst), 4);
+
+    let dir = std::env::temp_dir().join("mergebom_test_lookup_cache");
+    let _ = std::fs::remove_dir_all(&dir);
+    let lookup = PartLookup::new(backend).with_cache(DiskCache::new(&dir, Duration::from_secs(60)));
+    for _ in 0..3 {
+        assert_eq!(lookup.lookup("LM358D/R").unwrap().unwrap().stock, Some(100));
+        assert_eq!(lookup.lookup("NOPE").unwrap(), None);
+    }
+    // Only the first round reaches the server
+    assert_eq!(count.load(Ordering::SeqCst), 6);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(HttpBackend::new("https://example.com/api").is_ok());
+    assert!(HttpBackend::new("ftp://example.com").is_err());
+}
+
+#[test]
+fn api_key_stays_on_its_host() {
+    let (other, other_keys) = key_server(None);
+    let (url, keys) = key_server(Some(format!("{}/parts/NOPE", other)));
+    let backend = HttpBackend::new(&url)
+        .unwrap()
+        .with_api_key("secret")
+        .with_timeout(Duration::from_secs(2));
+    let secret = Some("secret".to_string());
+
+    assert_eq!(backend.fetch("HOME").unwrap(), None);
+    assert_eq!(*keys.lock().unwrap(), [secret.clone(), secret.clone()]);
+
+    assert_eq!(backend.fetch("AWAY").unwrap(), None);
+    assert_eq!(keys.lock().unwrap().len(), 3);
+    assert_eq!(*other_keys.lock().unwrap(), [None]);
+
+    let err = backend.fetch("HUGE").unwrap_err();
+    assert!(err.to_string().contains("bytes"));
+}
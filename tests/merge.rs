@@ -1,6 +1,7 @@
-use mergebom_web::bom::Bom;
+use mergebom_web::bom::{Bom, BomFormat, Item, ItemsTable, SortBy, Variant};
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Cursor};
 
 const TEST_DIR: &str = "tests/data";
 const CHECK_DIR: &str = "tests/data";
@@ -15,6 +16,11 @@ fn dump(v: &[String]) {
 
 fn test_run(test: &str, check: &str, merge_keys: &[String]) {
     let t = format!("{}/{}", TEST_DIR, test);
+    let bom = Bom::loader(&[t], merge_keys);
+    check_table(&bom.merge().odered_vector_table(), check);
+}
+
+fn check_table(data: &ItemsTable, check: &str) {
     let c = format!("{}/{}", CHECK_DIR, check);
 
     let mut checks = vec![];
@@ -26,9 +32,7 @@ fn test_run(test: &str, check: &str, merge_keys: &[String]) {
     }
 
     let mut results = vec![];
-    let bom = Bom::loader(&[t], merge_keys);
-    let data = bom.merge().odered_vector_table();
-    for c in data.rows.iter() {
+    for c in data.rows.iter().chain(data.dnp.iter()) {
         results.push(format!("{:}", c));
     }
     results.push(data.headers.join(";"));
@@ -50,6 +54,194 @@ fn merge_tests() {
     test_run("test1.csv", "test1.check", &["comment"].map(String::from));
 }
 
+#[test]
+fn dnp() {
+    test_run("test6.csv", "test6.check", &["comment"].map(String::from));
+}
+
+#[test]
+fn variants() {
+    let mut bom = Bom::loader(
+        &[format!("{}/test0.csv", TEST_DIR)],
+        &["comment"].map(String::from),
+    );
+    bom.add_variant(Variant {
+        name: "base".to_string(),
+        unfitted: vec!["C1".to_string()],
+        ..Default::default()
+    });
+    bom.add_variant(Variant {
+        name: "pro".to_string(),
+        substitutions: HashMap::from([(
+            "R1".to_string(),
+            HashMap::from([("comment".to_string(), "10k".to_string())]),
+        )]),
+        ..Default::default()
+    });
+
+    check_table(
+        &bom.variant("base").unwrap().merge().odered_vector_table(),
+        "test7.check",
+    );
+    check_table(
+        &bom.variants_table(SortBy::Designator).unwrap(),
+        "test8.check",
+    );
+    assert!(bom.variant("lite").is_err());
+}
+
+#[test]
+fn sources() {
+    let bom = Bom::loader(
+        &[
+            format!("{}/test0.csv", TEST_DIR),
+            format!("{}/test9.csv", TEST_DIR),
+        ],
+        &["comment", "footprint"].map(String::from),
+    );
+    let mut data = bom.merge().odered_vector_table();
+
+    let res = data
+        .rows
+        .iter()
+        .find(|r| r.unique_id == "** R Resistors **-1k-0603_[1608]")
+        .unwrap();
+    let mut rows: Vec<_> = res
+        .sources
+        .iter()
+        .map(|s| (s.file.as_str(), s.row, s.designators.join(",")))
+        .collect();
+    rows.sort();
+    assert_eq!(
+        rows,
+        [
+            ("test0.csv", 4, "R0".to_string()),
+            ("test0.csv", 5, "R1".to_string()),
+            ("test9.csv", 4, "R0".to_string()),
+            ("test9.csv", 5, "R1".to_string()),
+            ("test9.csv", 6, "R2".to_string()),
+        ]
+    );
+
+    data.add_source_columns();
+    assert_eq!(
+        data.headers[data.headers.len() - 2..],
+        ["test0.csv", "test9.csv"]
+    );
+    let res = data
+        .rows
+        .iter()
+        .find(|r| r.unique_id == "** R Resistors **-1k-0603_[1608]")
+        .unwrap();
+    assert_eq!(res.fields[res.fields.len() - 2..], ["2", "3"]);
+}
+
+#[test]
+fn sort_order() {
+    let bom = Bom::loader(
+        &[format!("{}/test10.csv", TEST_DIR)],
+        &["comment"].map(String::from),
+    );
+    let order = |sort: SortBy| -> Vec<String> {
+        bom.merge()
+            .odered_vector_table_by(sort)
+            .rows
+            .iter()
+            .map(|r| r.fields[1].clone())
+            .collect()
+    };
+
+    assert_eq!(order(SortBy::Designator), ["R1", "R2, R3", "R10", "C1", ""]);
+    assert_eq!(order(SortBy::Value), ["R2, R3", "R10", "R1", "C1", ""]);
+    assert_eq!(order(SortBy::Quantity), ["R2, R3", "R1", "R10", "C1", ""]);
+    // Same order on every run
+    assert_eq!(order(SortBy::Value), order(SortBy::Value));
+}
+
+#[test]
+fn from_memory() {
+    let keys = ["comment"].map(String::from);
+    let data = std::fs::read(format!("{}/test1.csv", TEST_DIR)).unwrap();
+    let bom = Bom::from_reader(Cursor::new(data), BomFormat::Csv, &keys).unwrap();
+    check_table(&bom.merge().odered_vector_table(), "test1.check");
+
+    let mut bom = Bom::from_items(
+        [
+            Item::new()
+                .with_designators(["C0"])
+                .with_comment("100nF")
+                .with_footprint("0603_[1608]")
+                .with_description("Ceramic"),
+            Item::new()
+                .with_designators(["R0", "R1"])
+                .with_comment("1k")
+                .with_footprint("0603_[1608]")
+                .with_description("Resistor"),
+        ],
+        &keys,
+    );
+    bom.push(
+        Item::new()
+            .with_designators(["C1"])
+            .with_comment("100nF")
+            .with_footprint("0603_[1608]")
+            .with_description("Ceramic"),
+    );
+    assert_eq!(bom.len(), 3);
+    assert_eq!(
+        bom.items().map(|i| i.quantity()).collect::<Vec<_>>(),
+        [1, 2, 1]
+    );
+
+    let merged = bom.merge();
+    assert_eq!(merged.len(), 2);
+    let caps = merged
+        .items()
+        .find(|i| i.unique_id() == "** C Capacitors **-100nF")
+        .unwrap();
+    assert_eq!(caps.designators(), ["C0", "C1"]);
+}
+
+#[test]
+fn part_numbers() {
+    test_run(
+        "test11.csv",
+        "test11.check",
+        &["comment", "footprint"].map(String::from),
+    );
+
+    let t = format!("{}/test11.csv", TEST_DIR);
+    let bom = Bom::loader(&[t], &["mpn"].map(String::from)).merge();
+    let mut lines: Vec<(Vec<String>, usize)> = bom
+        .items()
+        .filter(|i| !i.designators().is_empty())
+        .map(|i| (i.designators().to_vec(), i.alternates().len()))
+        .collect();
+    lines.sort();
+    assert_eq!(
+        lines,
+        [
+            (vec!["C1".to_string()], 1),
+            (vec!["C2".to_string()], 0),
+            (
+                vec!["R1".to_string(), "R2".to_string(), "R4".to_string()],
+                1
+            ),
+            (vec!["R3".to_string()], 0),
+        ]
+    );
+
+    let item = Item::new()
+        .with_designators(["C1"])
+        .with_manufacturer("Murata")
+        .with_mpn("GRM188R71H104KA93D")
+        .with_alternate("Kemet", "C0603C104K5RACTU")
+        .with_alternate("Murata", "GRM188R71H104KA93D")
+        .with_alternate("Kemet", "C0603C104K5RACTU");
+    assert_eq!(item.alternates().len(), 1);
+    assert_eq!(item.alternates()[0].manufacturer, "Kemet");
+}
+
 // #[test]
 // fn connector() {
 //     test_run(
@@ -67,3 +259,53 @@ fn merge_tests() {
 //         &["comment", "footprint", "description"].map(String::from),
 //     );
 // }
+
+#[test]
+fn variant_fits_np_part() {
+    let mut bom = Bom::loader(
+        &[format!("{}/test6.csv", TEST_DIR)],
+        &["comment"].map(String::from),
+    );
+    bom.add_variant(Variant {
+        name: "full".to_string(),
+        fitted: vec!["R1".to_string()],
+        ..Default::default()
+    });
+    let data = bom.variant("full").unwrap().merge().odered_vector_table();
+    assert!(data.rows.iter().any(|r| r.fields[1] == "R1"));
+    assert!(data.dnp.iter().all(|r| !r.fields[1].contains("R1")));
+}
+
+#[test]
+fn variants_without_merge_keys() {
+    let mut bom = Bom::loader(&[format!("{}/test0.csv", TEST_DIR)], &[]);
+    bom.add_variant(Variant {
+        name: "base".to_string(),
+        ..Default::default()
+    });
+    bom.add_variant(Variant {
+        name: "pro".to_string(),
+        substitutions: HashMap::from([(
+            "J1".to_string(),
+            HashMap::from([("note assembly".to_string(), "glue".to_string())]),
+        )]),
+        ..Default::default()
+    });
+    let data = bom.variants_table(SortBy::Designator).unwrap();
+    // A line is on one row with a quantity per variant
+    let r0: Vec<_> = data.rows.iter().filter(|r| r.fields[2] == "R0").collect();
+    assert_eq!(r0.len(), 1);
+    assert_eq!(r0[0].fields[..2], ["1", "1"]);
+    // The field added by "pro" has its own header
+    let note = data
+        .headers
+        .iter()
+        .position(|h| h == "Note assembly")
+        .unwrap();
+    assert!(data
+        .rows
+        .iter()
+        .all(|r| r.fields.len() == data.headers.len()));
+    let j1 = data.rows.iter().find(|r| r.fields[2] == "J1").unwrap();
+    assert_eq!(j1.fields[note], "GLUE");
+}
@@ -0,0 +1,337 @@
+use calamine::{DataType, Range, Reader, Xlsx};
+use mergebom_web::bom::{Bom, Diagnostic, ItemsTable, Severity, SortBy};
+use mergebom_web::cost::PriceList;
+use mergebom_web::outjob::{
+    OutFormat, OutJob, OutJobCsv, OutJobHtml, OutJobJson, OutJobMarkdown, OutJobMeta, OutJobXlsx,
+    XlsxOptions, XlsxSheets,
+};
+use mergebom_web::project::MergeProject;
+use mergebom_web::style::XlsxStyle;
+use std::io::{Cursor, Read};
+use std::str::FromStr;
+
+fn table() -> (ItemsTable, OutJobMeta) {
+    let mut prj = MergeProject::new(
+        &["tests/data/test13.csv"],
+        &["comment", "footprint"].map(String::from),
+    );
+    prj.options.sort_by = SortBy::Designator;
+    prj.options.revision = "A".to_string();
+    let data = prj.run().unwrap();
+    (data, prj.out_job_meta("Board | rev A"))
+}
+
+/// Sheets of an xlsx written in memory, read back.
+fn read_xlsx(job: OutJobXlsx) -> Xlsx<Cursor<Vec<u8>>> {
+    Xlsx::new(Cursor::new(job.into_bytes().unwrap())).unwrap()
+}
+
+fn sheet(wk: &mut Xlsx<Cursor<Vec<u8>>>, name: &str) -> Range<DataType> {
+    wk.worksheet_range(name).unwrap().unwrap()
+}
+
+fn first_sheet(bytes: &[u8]) -> Range<DataType> {
+    let mut wk = Xlsx::new(Cursor::new(bytes.to_vec())).unwrap();
+    wk.worksheet_range_at(0).unwrap().unwrap()
+}
+
+/// Xml of the first sheet, calamine does not tell merged cells and outlines.
+fn sheet_xml(bytes: &[u8]) -> String {
+    let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+    let mut xml = String::new();
+    zip.by_name("xl/worksheets/sheet1.xml")
+        .unwrap()
+        .read_to_string(&mut xml)
+        .unwrap();
+    xml
+}
+
+/// Ranges of the merged cells of the first sheet, eg. "A2:G2".
+fn merged_cells(bytes: &[u8]) -> Vec<String> {
+    sheet_xml(bytes)
+        .split("<mergeCell ")
+        .skip(1)
+        .filter_map(|m| m.split('"').nth(1).map(String::from))
+        .collect()
+}
+
+fn render<J: OutJob>(mut job: J, data: &ItemsTable, meta: &OutJobMeta) {
+    job.write(data, meta).unwrap();
+}
+
+#[test]
+fn text_formats() {
+    let (data, meta) = table();
+
+    let mut csv = Vec::new();
+    render(OutJobCsv::new(&mut csv), &data, &meta);
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), data.rows.len() + 1);
+    assert!(lines[0].starts_with("Quantity,Designator"));
+
+    let mut json = Vec::new();
+    render(OutJobJson::new(&mut json), &data, &meta);
+    let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(value["meta"]["title"], "Board | rev A");
+    let back: ItemsTable = serde_json::from_value(value["table"].clone()).unwrap();
+    assert_eq!(back, data);
+
+    let mut md = Vec::new();
+    render(OutJobMarkdown::new(&mut md), &data, &meta);
+    let md = String::from_utf8(md).unwrap();
+    assert!(md.starts_with("# Board | rev A\n"));
+    assert!(md.contains("## R Resistors\n"));
+    assert!(md.contains("| 1 | R1 |"));
+
+    let mut html = Vec::new();
+    render(OutJobHtml::new(&mut html), &data, &meta);
+    let html = String::from_utf8(html).unwrap();
+    assert!(html.contains("<title>Board | rev A</title>"));
+    assert!(html.contains("<td>R1</td>"));
+}
+
+#[test]
+fn format_names() {
+    assert_eq!(
+        OutFormat::from_str("markdown").unwrap(),
+        OutFormat::Markdown
+    );
+    assert_eq!(OutFormat::default(), OutFormat::Xlsx);
+    assert_eq!(OutFormat::Markdown.extension(), "md");
+    assert!(OutFormat::from_str("pdf").is_err());
+    assert_eq!(OutFormat::from_str("report").unwrap().extension(), "html");
+}
+
+#[test]
+fn printable_report() {
+    let (mut data, meta) = table();
+    data.diagnostics.push(Diagnostic {
+        unique_id: "U1".to_string(),
+        severity: Severity::Warning,
+        message: "obsolete <part>".to_string(),
+    });
+    let html = OutFormat::Report
+        .to_bytes(&data, &meta, &Default::default())
+        .unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert!(html.contains("Revision A"));
+    assert!(html.contains("@media print"));
+    assert!(html.contains("<h2>R Resistors</h2>"));
+    assert!(html.contains("<li class=\"warning\">"));
+    assert!(html.contains("obsolete &lt;part&gt;"));
+    assert!(!html.contains("<link"));
+}
+
+#[test]
+fn xlsx_path_and_memory() {
+    let (data, meta) = table();
+
+    let path = std::env::temp_dir().join("mergebom_test_outjob.xlsx");
+    let mut job = OutJobXlsx::new(&path).unwrap();
+    job.write(&data, &meta).unwrap();
+    assert!(path.is_file());
+    assert!(!path.with_extension("xlsx.xlsx").exists());
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(OutJobXlsx::new("/no/such/dir/bom.xlsx").is_err());
+
+    let mut job = OutJobXlsx::in_memory().unwrap();
+    let tmp = job.path().to_path_buf();
+    // In a directory of its own, not straight in the shared temp one
+    let dir = tmp.parent().unwrap().to_path_buf();
+    assert_ne!(dir, std::env::temp_dir());
+    job.write(&data, &meta).unwrap();
+    let bytes = job.into_bytes().unwrap();
+    assert!(bytes.starts_with(b"PK"));
+    assert!(!tmp.exists());
+    assert!(!dir.exists());
+
+    let csv = OutFormat::Csv
+        .to_bytes(&data, &meta, &Default::default())
+        .unwrap();
+    assert!(csv.starts_with(b"Quantity,"));
+}
+
+#[test]
+fn assembly_workbook() {
+    let (data, meta) = table();
+    assert_eq!(meta.revision, "A");
+    assert_eq!(meta.date.len(), 10);
+    assert_eq!(meta.inputs[0].name, "test13.csv");
+    assert_eq!(meta.inputs[0].crc32.len(), 8);
+
+    let mut data = data;
+    data.dnp.push(data.rows[0].clone());
+    let xlsx = XlsxOptions {
+        sheets: XlsxSheets::all(),
+        ..Default::default()
+    };
+    let bytes = OutFormat::Xlsx.to_bytes(&data, &meta, &xlsx).unwrap();
+    let mut wk = Xlsx::new(Cursor::new(bytes)).unwrap();
+    assert_eq!(
+        wk.sheet_names(),
+        ["Cover", "BOM", "DNP", "Diagnostics", "Sources"]
+    );
+    let cover = sheet(&mut wk, "Cover");
+    let text = |r, c| cover.get((r, c)).and_then(|d| d.get_string());
+    assert_eq!(text(3, 0), Some("Merge keys"));
+    assert_eq!(text(3, 1), Some("comment, footprint"));
+    assert_eq!(text(5, 1), Some("CRC32"));
+    assert_eq!(text(6, 0), Some("test13.csv"));
+    assert_eq!(text(6, 1), Some(meta.inputs[0].crc32.as_str()));
+}
+
+#[test]
+fn xlsx_style() {
+    let style = XlsxStyle::load("tests/data/style0.toml").unwrap();
+    assert_eq!(style.quantity_format, "0");
+    assert_eq!(style.category_color, "yellow");
+    assert!(style.freeze_header && style.autofilter);
+    assert!(style.category_rows && style.subtotals && style.outline);
+
+    let (data, meta) = table();
+    let widths = style.column_widths(&data.headers, &data.rows);
+    let column = |h: &str| data.headers.iter().position(|x| x == h).unwrap();
+    assert_eq!(widths[column("Description")], Some(40.0));
+    assert_eq!(widths[column("Designator")], Some(12.0));
+    assert_eq!(
+        XlsxStyle::default().column_widths(&data.headers, &data.rows)[0],
+        None
+    );
+
+    let mut job = OutJobXlsx::in_memory().unwrap().with_style(style);
+    job.write(&data, &meta).unwrap();
+
+    let path = std::env::temp_dir().join("mergebom_test_style.toml");
+    std::fs::write(&path, "header_color = \"ultraviolet\"\n").unwrap();
+    assert!(XlsxStyle::load(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn category_sections() {
+    let (mut data, meta) = table();
+    // Categories split across the table are gathered
+    let r = data.rows.remove(0);
+    data.rows.push(r);
+    assert!(data.rows[0].category.contains("Resistors"));
+    assert!(data.rows.last().unwrap().category.contains("Resistors"));
+
+    let mut md = Vec::new();
+    render(OutJobMarkdown::new(&mut md), &data, &meta);
+    let md = String::from_utf8(md).unwrap();
+    assert_eq!(md.matches("## R Resistors\n").count(), 1);
+    assert_eq!(md.matches("## U ").count(), 1);
+
+    let mut style = XlsxStyle {
+        category_rows: false,
+        subtotals: true,
+        ..Default::default()
+    };
+    let xlsx = |style: &XlsxStyle, data: &ItemsTable| {
+        let xlsx = XlsxOptions {
+            style: style.clone(),
+            ..Default::default()
+        };
+        OutFormat::Xlsx.to_bytes(data, &meta, &xlsx).unwrap()
+    };
+    let parts: usize = data.parts();
+    let lines = data.rows.len();
+
+    // One subtotal after the lines, no category row
+    let bytes = xlsx(&style, &data);
+    let bom = first_sheet(&bytes);
+    let text = |r: usize, c| bom.get((r, c)).and_then(|d| d.get_string());
+    assert_eq!(text(1, 1), Some(data.rows[0].fields[1].as_str()));
+    assert!((1..=lines).all(|r| !text(r, 0).unwrap_or_default().starts_with("**")));
+    assert_eq!(
+        bom.get((lines + 1, 0)),
+        Some(&DataType::Float(parts as f64))
+    );
+    assert_eq!(
+        text(lines + 1, 1),
+        Some(format!("{} lines", lines).as_str())
+    );
+    assert!(merged_cells(&bytes).is_empty());
+
+    // A category row across the table and a subtotal for each category
+    style.category_rows = true;
+    style.outline = true;
+    let bytes = xlsx(&style, &data);
+    let bom = first_sheet(&bytes);
+    let text = |r: usize, c| bom.get((r, c)).and_then(|d| d.get_string());
+    let last = (b'A' + data.headers.len() as u8 - 1) as char;
+    let merged = merged_cells(&bytes);
+    let mut row = 1;
+    let mut categories: Vec<&str> = Vec::new();
+    for r in data.rows.iter() {
+        if !categories.contains(&r.category.as_str()) {
+            categories.push(&r.category);
+        }
+    }
+    assert_eq!(merged.len(), categories.len());
+    for category in categories {
+        let group: Vec<_> = data
+            .rows
+            .iter()
+            .filter(|r| r.category == category)
+            .collect();
+        assert_eq!(text(row, 0), Some(category));
+        assert!(merged.contains(&format!("A{}:{}{}", row + 1, last, row + 1)));
+        row += 1 + group.len();
+        let parts: usize = group
+            .iter()
+            .map(|r| r.fields[0].parse::<usize>().unwrap())
+            .sum();
+        let lines = match group.len() {
+            1 => "1 line".to_string(),
+            n => format!("{} lines", n),
+        };
+        assert_eq!(bom.get((row, 0)), Some(&DataType::Float(parts as f64)));
+        assert_eq!(text(row, 1), Some(lines.as_str()));
+        row += 1;
+    }
+    assert_eq!(row, bom.height());
+    assert_eq!(
+        sheet_xml(&bytes).matches("outlineLevel=\"1\"").count(),
+        lines
+    );
+
+    // A single column has nothing to merge
+    data.headers.truncate(1);
+    for r in data.rows.iter_mut() {
+        r.fields.truncate(1);
+    }
+    let bytes = xlsx(&style, &data);
+    assert!(merged_cells(&bytes).is_empty());
+    assert_eq!(
+        first_sheet(&bytes).get((1, 0)),
+        Some(&DataType::String(data.rows[0].category.clone()))
+    );
+}
+
+#[test]
+fn cost_summary_column() {
+    let prices = PriceList::load("tests/data/prices0.csv").unwrap();
+    let bom = Bom::loader(
+        &["tests/data/test12.csv"],
+        &["comment", "footprint"].map(String::from),
+    );
+    let mut data = bom.merge().odered_vector_table();
+    data.add_costs(&prices, 10);
+    // Source quantities go after the prices
+    data.add_source_columns();
+    let column = data.extended_price_column().unwrap();
+    assert!(column < data.headers.len() - 1);
+
+    let mut job = OutJobXlsx::in_memory().unwrap();
+    job.write(&data, &OutJobMeta::default()).unwrap();
+    let bom = sheet(&mut read_xlsx(job), "BOM");
+    let row = (0..bom.height())
+        .find(|r| bom.get((*r, column - 1)) == Some(&DataType::String("Build quantity".into())))
+        .unwrap();
+    assert_eq!(bom.get((row, column)), Some(&DataType::Float(10.0)));
+    let total = bom.get((row + 3, column)).unwrap();
+    assert!((total.get_float().unwrap() - 1.90).abs() < 1e-9);
+}
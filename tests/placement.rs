@@ -0,0 +1,119 @@
+use mergebom_web::bom::{Bom, Layer, Severity};
+use mergebom_web::placement::Placements;
+
+const TEST_DIR: &str = "tests/data";
+
+fn bom() -> Bom {
+    let t = format!("{}/pnp_bom.csv", TEST_DIR);
+    Bom::loader(&[t], &["comment", "footprint"].map(String::from)).merge()
+}
+
+#[test]
+fn load_formats() {
+    let altium = Placements::load(format!("{}/pnp0.csv", TEST_DIR)).unwrap();
+    assert_eq!(altium.len(), 5);
+    let u1 = altium.get("U1").unwrap();
+    assert_eq!((u1.x, u1.y, u1.rotation), (30.0, 25.0, 45.0));
+    assert_eq!(u1.side, Layer::Top);
+
+    let kicad = Placements::load(format!("{}/pnp0.pos", TEST_DIR)).unwrap();
+    assert_eq!(kicad.len(), 4);
+    let c1 = kicad.get("C1").unwrap();
+    assert_eq!((c1.x, c1.y, c1.rotation), (12.5, -20.0, 180.0));
+    assert_eq!(c1.side, Layer::Bottom);
+    assert_eq!(c1.footprint, "C_0603_1608Metric");
+
+    // Inches from the unit line
+    let inches = Placements::load(format!("{}/pnp1.pos", TEST_DIR)).unwrap();
+    let c1 = inches.get("C1").unwrap();
+    assert!((c1.x - 25.4).abs() < 1e-9 && (c1.y + 6.35).abs() < 1e-9);
+    assert_eq!(c1.rotation, 180.0);
+
+    // Mils are converted, a placement is kept once
+    let rows = [
+        vec!["RefDes", "X (mil)", "Y (mil)", "TB"],
+        vec!["R1", "1000", "500", "B"],
+        vec!["R1", "0", "0", "T"],
+    ];
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|r| r.iter().map(|c| c.to_string()).collect())
+        .collect();
+    let pnp = Placements::from_rows(&rows).unwrap();
+    assert_eq!(pnp.len(), 1);
+    let r1 = pnp.get("R1").unwrap();
+    assert!((r1.x - 25.4).abs() < 1e-9 && (r1.y - 12.7).abs() < 1e-9);
+    assert_eq!(r1.side, Layer::Bottom);
+    assert!(Placements::from_rows(&rows[1..]).is_err());
+
+    // A side not understood is not taken as top
+    let rows = [
+        vec!["Designator", "X", "Y", "Layer"],
+        vec!["R1", "1", "1", "Bot"],
+        vec!["R2", "1", "1", "2"],
+    ];
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|r| r.iter().map(|c| c.to_string()).collect())
+        .collect();
+    let pnp = Placements::from_rows(&rows).unwrap();
+    assert_eq!(pnp.get("R1").unwrap().side, Layer::Bottom);
+    assert!(pnp.get("R2").is_none());
+}
+
+#[test]
+fn cross_check() {
+    let bom = bom();
+    let altium = Placements::load(format!("{}/pnp0.csv", TEST_DIR)).unwrap();
+    let diags = bom.check_placements(&altium);
+    let find = |text: &str| {
+        diags
+            .iter()
+            .find(|d| d.message.contains(text))
+            .unwrap_or_else(|| panic!("no {} in {:?}", text, diags))
+    };
+    assert_eq!(find("R2: no placement").severity, Severity::Error);
+    assert_eq!(
+        find("C1: Bottom in the BOM, Top in the placement").severity,
+        Severity::Error
+    );
+    assert_eq!(
+        find("U2: placed but not populated").severity,
+        Severity::Warning
+    );
+    assert_eq!(find("TP1: placed but not in the BOM").unique_id, "TP1");
+    assert_eq!(diags.len(), 4);
+
+    let kicad = Placements::load(format!("{}/pnp0.pos", TEST_DIR)).unwrap();
+    assert!(bom.check_placements(&kicad).is_empty());
+}
+
+#[test]
+fn centroid_per_side() {
+    let bom = bom();
+    let pnp = Placements::load(format!("{}/pnp0.csv", TEST_DIR)).unwrap();
+    let centroid = bom.centroid(&pnp);
+    // Not populated and unknown parts are left out
+    assert_eq!(centroid.len(), 3);
+    assert!(centroid.get("U2").is_none() && centroid.get("TP1").is_none());
+    let u1 = centroid.get("U1").unwrap();
+    assert_eq!(u1.value, "STM32F401");
+    assert_eq!(u1.mpn, "STM32F401RET6");
+
+    let mut top = Vec::new();
+    assert_eq!(centroid.write_csv(Layer::Top, &mut top).unwrap(), 3);
+    let top = String::from_utf8(top).unwrap();
+    let lines: Vec<&str> = top.lines().collect();
+    assert_eq!(
+        lines[0],
+        "Designator,Value,Footprint,MPN,X (mm),Y (mm),Rotation,Side"
+    );
+    assert_eq!(
+        lines[1],
+        "C1,100nF,0603,GRM188R71H104KA93D,12.5000,20.0000,0.00,Top"
+    );
+    assert!(lines[3].starts_with("U1,STM32F401,LQFP64"));
+
+    let mut bottom = Vec::new();
+    assert_eq!(centroid.write_csv(Layer::Bottom, &mut bottom).unwrap(), 0);
+}
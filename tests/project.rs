@@ -0,0 +1,107 @@
+use mergebom_web::attrition::{AttritionRule, AttritionRules};
+use mergebom_web::bom::{SortBy, Variant};
+use mergebom_web::project::{MergeProject, ProjectFormat, PROJECT_VERSION};
+
+fn test_project() -> MergeProject {
+    let mut prj = MergeProject::new(
+        &["tests/data/test1.csv", "tests/data/test6.csv"],
+        &["comment".to_string()],
+    );
+    prj.options.sort_by = SortBy::Quantity;
+    prj.options.source_columns = true;
+    prj.options.variants.push(Variant {
+        name: "lite".to_string(),
+        unfitted: vec!["R1".to_string()],
+        ..Default::default()
+    });
+    prj.options.attrition = AttritionRules::new(vec![AttritionRule {
+        footprint: "0603".to_string(),
+        percent: 10.0,
+        min_extra: 20,
+        ..Default::default()
+    }]);
+    prj
+}
+
+#[test]
+fn round_trip() {
+    let mut prj = test_project();
+    let data = prj.run().unwrap();
+    assert!(!data.rows.is_empty());
+
+    for format in [ProjectFormat::Json, ProjectFormat::Toml] {
+        let text = prj.to_string(format).unwrap();
+        let loaded = MergeProject::from_str(&text, format).unwrap();
+        assert_eq!(loaded, prj);
+        assert_eq!(loaded.table().unwrap(), data);
+    }
+}
+
+#[test]
+fn save_and_load() {
+    let mut prj = test_project();
+    let data = prj.run().unwrap();
+
+    let path = std::env::temp_dir().join("mergebom_test_project.toml");
+    prj.save(&path).unwrap();
+    let mut loaded = MergeProject::load(&path).unwrap();
+    assert_eq!(loaded.run().unwrap(), data);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(prj.save(std::env::temp_dir().join("project.txt")).is_err());
+}
+
+#[test]
+fn version_check() {
+    let prj = test_project();
+    assert!(prj.result.is_none());
+    assert!(prj.table().is_err());
+
+    let text = prj.to_string(ProjectFormat::Json).unwrap();
+    let newer = text.replace(
+        &format!("\"version\": {}", PROJECT_VERSION),
+        &format!("\"version\": {}", PROJECT_VERSION + 1),
+    );
+    assert!(MergeProject::from_str(&newer, ProjectFormat::Json).is_err());
+    assert!(MergeProject::from_str("{\"inputs\": []}", ProjectFormat::Json).is_err());
+}
+
+#[test]
+fn migrate_bare_bom() {
+    let mut prj = MergeProject::new(&["tests/data/test1.csv"], &["comment".to_string()]);
+    let data = prj.run().unwrap();
+
+    // A Bom saved as is, before project files and sources existed
+    let mut bom = serde_json::to_value(prj.result.as_ref().unwrap()).unwrap();
+    bom.as_object_mut().unwrap().remove("variants");
+    for item in bom["items"].as_array_mut().unwrap() {
+        item.as_object_mut().unwrap().remove("sources");
+    }
+
+    let old = MergeProject::from_str(&bom.to_string(), ProjectFormat::Json).unwrap();
+    assert_eq!(old.version, PROJECT_VERSION);
+    assert!(old.inputs.is_empty());
+    assert_eq!(old.merge_keys, ["comment"]);
+
+    let table = old.table().unwrap();
+    assert_eq!(table.headers, data.headers);
+    assert_eq!(
+        table.rows.iter().map(|r| &r.fields).collect::<Vec<_>>(),
+        data.rows.iter().map(|r| &r.fields).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn missing_inputs() {
+    let mut prj = MergeProject::new(&["tests/data/moved.csv"], &["comment".to_string()]);
+    let err = prj.run().unwrap_err();
+    assert!(format!("{:#}", err).contains("tests/data/moved.csv"));
+    assert!(prj.result.is_none());
+
+    let mut prj = MergeProject::new(&["tests/data/test1"], &["comment".to_string()]);
+    assert!(prj
+        .run()
+        .unwrap_err()
+        .to_string()
+        .contains("tests/data/test1"));
+}
@@ -0,0 +1,160 @@
+use calamine::{DataType, Reader, Xlsx};
+use mergebom_web::bom::{Bom, BomFormat, ItemsTable};
+use mergebom_web::columns::{Column, ColumnSpec};
+use mergebom_web::cost::PriceList;
+use mergebom_web::outjob::{OutFormat, OutJobMeta, XlsxOptions, XlsxSheets};
+use mergebom_web::style::XlsxStyle;
+use std::io::Cursor;
+
+fn keys() -> Vec<String> {
+    ["comment", "footprint"].map(String::from).to_vec()
+}
+
+/// A merged BOM with costs, and our xlsx of it with every extra row on.
+fn merged() -> (ItemsTable, Vec<u8>) {
+    merged_with(&ColumnSpec::default())
+}
+
+fn merged_with(spec: &ColumnSpec) -> (ItemsTable, Vec<u8>) {
+    let prices = PriceList::load("tests/data/prices0.csv").unwrap();
+    let bom = Bom::loader(&["tests/data/test12.csv"], &keys());
+    let mut data = bom.merge().odered_vector_table();
+    data.add_costs(&prices, 10);
+    data.apply_columns(spec);
+    let xlsx = XlsxOptions {
+        sheets: XlsxSheets::all(),
+        style: XlsxStyle {
+            category_rows: true,
+            subtotals: true,
+            quantity_format: "0".to_string(),
+            ..Default::default()
+        },
+    };
+    let bytes = OutFormat::Xlsx
+        .to_bytes(&data, &OutJobMeta::default(), &xlsx)
+        .unwrap();
+    (data, bytes)
+}
+
+#[test]
+fn reimport_merged_xlsx() {
+    let (data, bytes) = merged();
+    // The line without designators is not read back
+    let lines: Vec<_> = data
+        .rows
+        .iter()
+        .filter(|r| !r.fields[1].is_empty())
+        .collect();
+    assert!(lines.len() < data.rows.len());
+    let wk = Xlsx::new(Cursor::new(bytes.clone())).unwrap();
+    assert_eq!(wk.sheet_names()[0], "Cover");
+
+    let bom = Bom::from_reader(Cursor::new(bytes), BomFormat::Xlsx, &keys()).unwrap();
+    let items: Vec<_> = bom.items().collect();
+    // Cover, category, subtotal, blank and cost rows are skipped
+    assert_eq!(items.len(), lines.len());
+    let column = data.quantity_column();
+    for (item, row) in items.iter().zip(lines) {
+        assert_eq!(item.quantity(), row.quantity_in(column));
+        assert_eq!(item.designators().join(", "), row.fields[1]);
+        assert_eq!(item.sources()[0].sheet, "BOM");
+    }
+    assert_eq!(
+        items.iter().map(|i| i.quantity()).sum::<usize>(),
+        data.parts()
+    );
+}
+
+#[test]
+fn reimport_reordered_xlsx() {
+    // Category titles and the cost summary land under real headers, and
+    // the build quantity right in the quantity column
+    let spec = ColumnSpec::new(vec![
+        Column::field("Designator"),
+        Column::field("Comment"),
+        Column::field("Footprint"),
+        Column::field("Quantity").with_label("Pcs"),
+    ]);
+    let (data, bytes) = merged_with(&spec);
+    assert_eq!(data.quantity_column(), Some(3));
+    assert!(data.extended_price_column().is_none());
+
+    let bom = Bom::from_reader(Cursor::new(bytes), BomFormat::Xlsx, &keys()).unwrap();
+    let items: Vec<_> = bom.items().collect();
+    let lines: Vec<_> = data
+        .rows
+        .iter()
+        .filter(|r| !r.fields[0].is_empty())
+        .collect();
+    assert_eq!(items.len(), lines.len());
+    for (item, row) in items.iter().zip(lines) {
+        assert_eq!(item.designators().join(", "), row.fields[0]);
+        assert_eq!(item.comment(), Some(row.fields[1].as_str()));
+        assert_eq!(item.quantity(), row.quantity_in(Some(3)));
+    }
+    assert_eq!(
+        items.iter().map(|i| i.quantity()).sum::<usize>(),
+        data.parts()
+    );
+}
+
+#[test]
+fn merge_merged_boms() {
+    let (data, bytes) = merged();
+    let t = std::env::temp_dir().join(format!("mergebom_roundtrip_{}.xlsx", std::process::id()));
+    std::fs::write(&t, bytes).unwrap();
+    let t = t.to_str().unwrap();
+    let board = "tests/data/roundtrip0.csv";
+
+    let bom = Bom::loader(&[t, board], &keys()).merge();
+    // C10 is the only new line
+    let lines = data.rows.iter().filter(|r| !r.fields[1].is_empty()).count();
+    assert_eq!(bom.items().count(), lines + 1);
+    let r = bom.items().find(|i| i.comment() == Some("10k")).unwrap();
+    assert_eq!(r.designators(), ["R1", "R2", "R3", "R10", "R11"]);
+    assert_eq!(r.quantity(), 5);
+    assert_eq!(
+        bom.items().map(|i| i.quantity()).sum::<usize>(),
+        data.parts() + 3
+    );
+
+    // The same merged BOM twice has the same designators
+    let twice = Bom::loader(&[t, t], &keys()).merge();
+    assert_eq!(
+        twice.items().map(|i| i.quantity()).sum::<usize>(),
+        data.parts()
+    );
+    std::fs::remove_file(t).unwrap();
+}
+
+#[test]
+fn other_bom_sheet() {
+    // Not ours: a "BOM" sheet without the defined name is not looked for
+    let path = std::env::temp_dir().join(format!("mergebom_other_{}.xlsx", std::process::id()));
+    let wk = xlsxwriter::Workbook::new(path.to_str().unwrap()).unwrap();
+    let mut info = wk.add_worksheet(Some("Info")).unwrap();
+    for (c, h) in ["Designator", "Comment", "Footprint"].iter().enumerate() {
+        info.write_string(0, c as u16, h, None).unwrap();
+    }
+    for (c, v) in ["R5", "1k", "0402"].iter().enumerate() {
+        info.write_string(1, c as u16, v, None).unwrap();
+    }
+    let mut sheet = wk.add_worksheet(Some("BOM")).unwrap();
+    sheet.write_string(0, 0, "Notes", None).unwrap();
+    wk.close().unwrap();
+
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut check = Xlsx::new(Cursor::new(bytes.clone())).unwrap();
+    assert!(check.defined_names().is_empty());
+    assert_eq!(
+        check.worksheet_range("BOM").unwrap().unwrap().get((0, 0)),
+        Some(&DataType::String("Notes".into()))
+    );
+
+    let bom = Bom::from_reader(Cursor::new(bytes), BomFormat::Xlsx, &keys()).unwrap();
+    let items: Vec<_> = bom.items().collect();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].designators(), ["R5"]);
+    assert_eq!(items[0].sources()[0].sheet, "Info");
+}